@@ -0,0 +1,118 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+//! Replays sessions shaped like the benchmarks in `/benchmarks` through the
+//! renderer's stages, and reports the throughput of each:
+//!
+//! - `decode`: receiving messages the way the server does, parsing them and
+//!   applying their resources and diffs.
+//! - `build`: walking every frame's display list into a backend.
+//!
+//! The sessions are described in `sessions/mod.rs`.
+
+#[macro_use]
+extern crate criterion;
+extern crate renderer_client;
+extern crate renderer_process;
+
+mod sessions;
+
+use criterion::{Benchmark, Criterion, Throughput};
+
+use renderer_process::backend::{self, FontTables, RecordingBackend};
+use renderer_process::display_list::RemoteDisplayItem;
+use renderer_process::protocol::{receive_clear, receive_message};
+use renderer_process::resources::RemoteResource;
+use renderer_process::shaping::ShapingCache;
+use renderer_process::state::{REMOTE_DISPLAY_LIST, REMOTE_RESOURCES};
+use renderer_process::text_runs::RunCache;
+
+/// Everything the build stage needs, prepared ahead of time so that it isn't
+/// measured along with it.
+struct Frames {
+    display_lists: Vec<Vec<RemoteDisplayItem>>,
+    fonts: FontTables
+}
+
+/// Receives every message in order, handing the display list to `frame`
+/// after each of them, then clears the renderer's state again. Returns the
+/// resources that were added along the way.
+fn decode<F>(messages: &[String], mut frame: F) -> Vec<RemoteResource>
+where
+    F: FnMut(&Vec<RemoteDisplayItem>)
+{
+    for body in messages {
+        receive_message(body).unwrap();
+        frame(&REMOTE_DISPLAY_LIST.lock().unwrap());
+    }
+
+    let resources = REMOTE_RESOURCES.lock().unwrap().clone();
+    receive_clear();
+    resources
+}
+
+fn prepare_frames(messages: &[String]) -> Frames {
+    let mut display_lists = vec![];
+    let resources = decode(messages, |display_list| display_lists.push(display_list.clone()));
+    let mut fonts = FontTables::default();
+
+    // There's no font backend here, so any family name will do.
+    for resource in resources {
+        match resource {
+            RemoteResource::Font(font) => {
+                let face = font.face();
+                fonts.descriptors.insert(font.key, font.descriptor(face.as_ref()));
+                fonts = fonts.font(font.key, &format!("font-{}", font.key), face);
+            }
+            RemoteResource::FontInstance(font_instance) => {
+                fonts = fonts.instance(font_instance.instance_key, font_instance.size);
+            }
+            _ => {}
+        }
+    }
+
+    Frames { display_lists, fonts }
+}
+
+fn build(frames: &Frames) -> usize {
+    let fonts = frames.fonts.fonts();
+    // One cache for the whole session, the way the runtime keeps it.
+    let mut shaping = ShapingCache::default();
+    let mut count = 0;
+    for display_list in &frames.display_lists {
+        // Text changes between the prepared frames aren't tracked, so runs
+        // are resolved afresh for each of them.
+        let mut recording = RecordingBackend::new();
+        backend::build(&mut recording, display_list, fonts, &mut RunCache::default(), &mut shaping);
+        count += recording.items.len();
+    }
+    count
+}
+
+fn replay(c: &mut Criterion) {
+    for (name, messages) in sessions::sessions() {
+        let bytes = messages.iter().map(|body| body.len() as u32).sum();
+        let frames = prepare_frames(&messages);
+        let items = frames.display_lists.iter().map(|display_list| display_list.len() as u32).sum();
+
+        c.bench(
+            name,
+            Benchmark::new("decode", move |b| b.iter(|| decode(&messages, |_| {}))).throughput(Throughput::Bytes(bytes))
+        );
+        c.bench(
+            name,
+            Benchmark::new("build", move |b| b.iter(|| build(&frames))).throughput(Throughput::Elements(items))
+        );
+    }
+}
+
+criterion_group!(benches, replay);
+criterion_main!(benches);
@@ -0,0 +1,182 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+//! Trimmed sessions shaped like the benchmarks in `/benchmarks`, so they can
+//! be replayed without a browser. They're built with the client's message
+//! builder, which keeps them in step with the protocol, and returned as the
+//! serialized messages a host would send:
+//!
+//! - `dbmonster-react`: a table of databases, where every row's query count
+//!   and the elapsed times of its top five queries change each frame.
+//! - `counter-ridiculous`: the same counter many times over, each with a
+//!   random digit that changes each frame.
+
+use renderer_client::{Bounds, DisplayListDiff, ItemChange, Message, ResourceUpdate, Rgba};
+use renderer_process::overlay;
+
+const FONT_KEY: u64 = 1;
+const FONT_INSTANCE_KEY: u64 = 2;
+const FRAMES: usize = 30;
+
+const DATABASES: u32 = 12;
+const QUERIES: u32 = 5;
+const COUNTERS: u32 = 50;
+const BUTTONS: &[&str] = &["+", "-", "Increment if odd", "Increment async"];
+
+/// Every session, by the name of the benchmark it's shaped like.
+pub fn sessions() -> Vec<(&'static str, Vec<String>)> {
+    vec![("dbmonster-react", dbmonster_react()), ("counter-ridiculous", counter_ridiculous())]
+}
+
+pub fn dbmonster_react() -> Vec<String> {
+    let mut random = Random(1);
+    let row_items = 3 + QUERIES * 2;
+    let mut first = start();
+    for row in 0..DATABASES {
+        let top = row * 20;
+        first = first
+            .diff(text(Bounds::new(0, top, 120, 20), &format!("cluster{}", row)))
+            .diff(rect(Bounds::new(120, top, 40, 20), random.color()))
+            .diff(text(Bounds::new(120, top, 40, 20), &random.count().to_string()));
+        for query in 0..QUERIES {
+            let left = 160 + query * 80;
+            first = first
+                .diff(rect(Bounds::new(left, top, 80, 20), random.color()))
+                .diff(text(Bounds::new(left, top, 80, 20), &random.elapsed()));
+        }
+    }
+
+    let mut messages = vec![first];
+    for _ in 1..FRAMES {
+        let mut frame = Message::new();
+        for row in 0..DATABASES {
+            let index = (row * row_items) as usize;
+            frame = frame.diff(update_text(index + 2, random.count().to_string()));
+            for query in 0..QUERIES {
+                frame = frame.diff(update_text(index + 4 + query as usize * 2, random.elapsed()));
+            }
+        }
+        messages.push(frame);
+    }
+    serialize(messages)
+}
+
+pub fn counter_ridiculous() -> Vec<String> {
+    let mut random = Random(2);
+    let counter_items = 2 + BUTTONS.len() as u32 * 2;
+    let mut first = start();
+    for counter in 0..COUNTERS {
+        let top = counter * 24;
+        first = first
+            .diff(text(Bounds::new(0, top, 80, 20), &format!("Random: {},", random.digit())))
+            .diff(text(Bounds::new(80, top, 120, 20), "clicked: 0 times"));
+        let mut left = 200;
+        for label in BUTTONS {
+            let width = 16 + label.len() as u32 * 8;
+            first = first
+                .diff(DisplayListDiff::AddBorder {
+                    bounds: Bounds::new(left, top, width, 20),
+                    colors: [gray(); 4]
+                })
+                .diff(text(Bounds::new(left + 8, top, width - 16, 20), label));
+            left += width + 4;
+        }
+    }
+
+    let mut messages = vec![first];
+    for _ in 1..FRAMES {
+        let mut frame = Message::new();
+        for counter in 0..COUNTERS {
+            let index = (counter * counter_items) as usize;
+            frame = frame.diff(update_text(index, format!("Random: {},", random.digit())));
+        }
+        messages.push(frame);
+    }
+    serialize(messages)
+}
+
+/// Starts a session afresh, with the overlay's font to draw text with.
+fn start() -> Message {
+    Message::new()
+        .clear()
+        .size(1024, 768)
+        .resource(ResourceUpdate::AddFont {
+            key: FONT_KEY,
+            data_uri: overlay::font_data_uri(),
+            descriptor: None
+        })
+        .resource(ResourceUpdate::AddFontInstance {
+            key: FONT_KEY,
+            instance_key: FONT_INSTANCE_KEY,
+            size: 12
+        })
+}
+
+fn serialize(messages: Vec<Message>) -> Vec<String> {
+    messages.iter().map(|message| message.to_json().to_string()).collect()
+}
+
+fn rect(bounds: Bounds, color: Rgba) -> DisplayListDiff {
+    DisplayListDiff::AddRect { bounds, color }
+}
+
+fn text(bounds: Bounds, text: &str) -> DisplayListDiff {
+    DisplayListDiff::AddText {
+        bounds,
+        color: Rgba::new(0, 0, 0, 255),
+        text: text.to_string(),
+        font_key: FONT_KEY,
+        font_instance_key: FONT_INSTANCE_KEY
+    }
+}
+
+fn update_text(index: usize, text: String) -> DisplayListDiff {
+    DisplayListDiff::UpdateSelf {
+        index,
+        changes: vec![ItemChange::Text(text)]
+    }
+}
+
+fn gray() -> Rgba {
+    Rgba::new(128, 128, 128, 255)
+}
+
+/// A xorshift generator, so that every run replays the same session.
+struct Random(u32);
+
+impl Random {
+    fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+
+    fn digit(&mut self) -> u32 {
+        self.next() % 9
+    }
+
+    fn count(&mut self) -> u32 {
+        self.next() % 20
+    }
+
+    fn elapsed(&mut self) -> String {
+        format!("{:.2}", (self.next() % 1500) as f32 / 100.0)
+    }
+
+    fn color(&mut self) -> Rgba {
+        match self.next() % 3 {
+            0 => Rgba::new(92, 184, 92, 255),
+            1 => Rgba::new(240, 173, 78, 255),
+            _ => Rgba::new(217, 83, 79, 255)
+        }
+    }
+}
@@ -0,0 +1,147 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use serde_json::{self, Value};
+use ws::{self, CloseCode, Handshake, Sender};
+
+use error::{Error, Result};
+use message::Message;
+
+const DEFAULT_TIMEOUT_MS: u64 = 5000;
+
+enum Event {
+    Open(Sender),
+    Reply(Value),
+    Error(ws::Error),
+    Closed
+}
+
+struct Handler {
+    out: Sender,
+    events: mpsc::Sender<Event>
+}
+
+impl ws::Handler for Handler {
+    fn on_open(&mut self, _: Handshake) -> ws::Result<()> {
+        let _ = self.events.send(Event::Open(self.out.clone()));
+        Ok(())
+    }
+
+    fn on_message(&mut self, msg: ws::Message) -> ws::Result<()> {
+        // Anything that isn't a JSON text message isn't a reply from the renderer.
+        if let Some(reply) = msg.as_text().ok().and_then(|text| serde_json::from_str(text).ok()) {
+            let _ = self.events.send(Event::Reply(reply));
+        }
+        Ok(())
+    }
+
+    fn on_close(&mut self, _: CloseCode, _: &str) {
+        let _ = self.events.send(Event::Closed);
+    }
+
+    fn on_error(&mut self, err: ws::Error) {
+        let _ = self.events.send(Event::Error(err));
+    }
+}
+
+/// A connection to a running renderer process. The websocket event loop runs
+/// on its own thread, so all methods here block until their work is done.
+/// The connection is closed when the client is dropped.
+pub struct Client {
+    out: Sender,
+    events: Receiver<Event>,
+    thread: Option<JoinHandle<()>>,
+    timeout: Duration
+}
+
+impl Client {
+    pub fn connect(url: &str) -> Result<Client> {
+        let url = url.to_string();
+        let (events_sender, events) = mpsc::channel();
+
+        let thread = thread::spawn(move || {
+            let result = ws::connect(url, |out| Handler {
+                out,
+                events: events_sender.clone()
+            });
+            if let Err(err) = result {
+                let _ = events_sender.send(Event::Error(err));
+            }
+        });
+
+        // If the connection fails the event loop exits, dropping the channel,
+        // usually once the handler has been told why.
+        match events.recv() {
+            Ok(Event::Open(out)) => Ok(Client {
+                out,
+                events,
+                thread: Some(thread),
+                timeout: Duration::from_millis(DEFAULT_TIMEOUT_MS)
+            }),
+            Ok(Event::Error(err)) => Err(Error::Connect(err)),
+            _ => Err(Error::Disconnected)
+        }
+    }
+
+    /// Sets how long `request` and `recv` wait for a reply before giving up.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn send(&self, message: &Message) -> Result<()> {
+        self.send_raw(&message.to_json().to_string())
+    }
+
+    /// Sends an already serialized message, as-is.
+    pub fn send_raw(&self, body: &str) -> Result<()> {
+        self.out.send(body)?;
+        Ok(())
+    }
+
+    /// Sends a message and waits for the renderer's reply to it. The
+    /// renderer replies in order and without saying what to, so replies
+    /// still queued from earlier messages, such as errors for messages sent
+    /// with `send`, are dropped first rather than taken for this one's.
+    pub fn request(&self, message: &Message) -> Result<Value> {
+        loop {
+            match self.events.try_recv() {
+                Ok(Event::Closed) | Err(TryRecvError::Disconnected) => return Err(Error::Disconnected),
+                Ok(_) => {}
+                Err(TryRecvError::Empty) => break
+            }
+        }
+        self.send(message)?;
+        self.recv()
+    }
+
+    pub fn recv(&self) -> Result<Value> {
+        match self.events.recv_timeout(self.timeout) {
+            Ok(Event::Reply(reply)) => Ok(reply),
+            Ok(Event::Open(_)) => self.recv(),
+            Ok(Event::Error(err)) => Err(Error::WebSocket(err)),
+            Ok(Event::Closed) | Err(RecvTimeoutError::Disconnected) => Err(Error::Disconnected),
+            Err(RecvTimeoutError::Timeout) => Err(Error::Timeout)
+        }
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        let _ = self.out.close(CloseCode::Normal);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
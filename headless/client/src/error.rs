@@ -0,0 +1,72 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+use std::error;
+use std::fmt;
+use std::result;
+
+use serde_json;
+use ws;
+
+pub type Result<T> = result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    WebSocket(ws::Error),
+    Json(serde_json::Error),
+    Connect(ws::Error),
+    Disconnected,
+    Timeout
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Error::WebSocket(ref err) => write!(f, "WebSocket error: {}", err),
+            &Error::Json(ref err) => write!(f, "JSON error: {}", err),
+            &Error::Connect(ref err) => write!(f, "Couldn't connect to the renderer process: {}", err),
+            &Error::Disconnected => write!(f, "Disconnected from the renderer process"),
+            &Error::Timeout => write!(f, "Timed out waiting for a reply")
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match self {
+            &Error::WebSocket(ref err) => err.description(),
+            &Error::Json(ref err) => err.description(),
+            &Error::Connect(_) => "connection failed",
+            &Error::Disconnected => "disconnected",
+            &Error::Timeout => "timeout"
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match self {
+            &Error::WebSocket(ref err) | &Error::Connect(ref err) => Some(err),
+            &Error::Json(ref err) => Some(err),
+            &Error::Disconnected | &Error::Timeout => None
+        }
+    }
+}
+
+impl From<ws::Error> for Error {
+    fn from(err: ws::Error) -> Self {
+        Error::WebSocket(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Json(err)
+    }
+}
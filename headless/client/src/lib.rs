@@ -0,0 +1,56 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+//! A client for the renderer process protocol. Build a `Message` out of
+//! resource updates and display list diffs, then send it over a `Client`:
+//!
+//! ```no_run
+//! use renderer_client::{Bounds, Client, DisplayListDiff, Message, Rgba};
+//!
+//! let client = Client::connect("ws://127.0.0.1:6767").unwrap();
+//! let rect = DisplayListDiff::AddRect {
+//!     bounds: Bounds::new(0, 0, 100, 100),
+//!     color: Rgba::new(255, 0, 0, 255)
+//! };
+//! client.send(&Message::new().clear().diff(rect)).unwrap();
+//! ```
+
+extern crate rsx_primitives;
+#[macro_use]
+extern crate serde_json;
+extern crate ws;
+
+mod connection;
+mod error;
+mod message;
+
+pub use connection::Client;
+pub use error::{Error, Result};
+pub use message::{
+    Bounds,
+    DecorationLine,
+    DecorationStyle,
+    DisplayListDiff,
+    FontDescriptor,
+    FontStyle,
+    Glyph,
+    ItemChange,
+    Message,
+    ResourceUpdate,
+    Rgba,
+    TextAlign,
+    TextDecoration,
+    TextDirection,
+    TextLayout,
+    TextOrientation,
+    TextOverflow,
+    TextRun
+};
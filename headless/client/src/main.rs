@@ -0,0 +1,209 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+extern crate base64;
+extern crate renderer_client;
+extern crate serde_json;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process;
+use std::time::Duration;
+
+use renderer_client::{Client, Error, Message};
+use serde_json::Value;
+
+const USAGE: &str = "\
+Usage: renderer-client [OPTIONS] <COMMAND>
+
+Commands:
+    send <FILE>           Send a JSON message, or every message in a recorded trace
+    raw <JSON>            Send a JSON message given on the command line
+    dump [FILE]           Print the renderer's display list snapshot, or write it to FILE
+    svg [FILE]            Print the SVG export of the display list, or write it to FILE
+    screenshot <FILE>     Save a software rendered approximation of the display list, with
+                          text drawn as blocks, to FILE as a PNG
+    stats [FILE]          Print the renderer's per-stage frame timings, or write them to FILE
+
+Options:
+    --address <URL>       Renderer to connect to (default: ws://127.0.0.1:6767)
+    --timeout <MS>        How long to wait for replies (default: 5000)
+    --help                Print this message";
+
+struct Options {
+    address: String,
+    timeout: Option<u64>,
+    command: String,
+    args: Vec<String>
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut address = "ws://127.0.0.1:6767".to_string();
+    let mut timeout = None;
+    let mut positional = vec![];
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--address" => address = args.next().ok_or("Missing value for `--address`")?,
+            "--timeout" => {
+                let value = args.next().ok_or("Missing value for `--timeout`")?;
+                timeout = Some(value.parse().map_err(|_| format!("Invalid timeout `{}`", value))?);
+            }
+            "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => positional.push(arg)
+        }
+    }
+
+    if positional.is_empty() {
+        return Err("Missing command".to_string());
+    }
+
+    let command = positional.remove(0);
+    Ok(Options {
+        address,
+        timeout,
+        command,
+        args: positional
+    })
+}
+
+fn read_file(path: &str) -> io::Result<String> {
+    let mut contents = String::new();
+    File::open(path)?.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+fn write_file(path: &str, contents: &[u8]) -> io::Result<()> {
+    File::create(path)?.write_all(contents)
+}
+
+/// Something to replay from a file given to `send`.
+enum Step {
+    Send(String),
+    Close
+}
+
+/// Returns the steps in `contents`: a trace recorded by the renderer process,
+/// when every line is a `{ time, event }` entry, and otherwise a single JSON
+/// message sent as is. A one line trace is still a trace, even though it
+/// parses as JSON too.
+fn steps_in(contents: &str) -> Vec<Step> {
+    let entries = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str::<Value>(line).ok().and_then(|entry| trace_step(&entry)))
+        .collect::<Option<Vec<_>>>();
+    entries.unwrap_or_else(|| vec![Step::Send(contents.to_string())])
+}
+
+/// The step a trace entry records, if `entry` is one.
+fn trace_step(entry: &Value) -> Option<Step> {
+    entry.get("time")?.as_u64()?;
+    match entry.get("event")? {
+        &Value::String(ref event) if event == "Close" => Some(Step::Close),
+        event => event.get("Message")?.as_str().map(|body| Step::Send(body.to_string()))
+    }
+}
+
+fn connect(options: &Options) -> Result<Client, String> {
+    let mut client = Client::connect(&options.address).map_err(|err| err.to_string())?;
+    if let Some(timeout) = options.timeout {
+        client.set_timeout(Duration::from_millis(timeout));
+    }
+    Ok(client)
+}
+
+/// Prints every reply received until the renderer goes quiet.
+fn print_replies(client: &Client) -> Result<(), String> {
+    loop {
+        match client.recv() {
+            Ok(reply) => println!("{}", serde_json::to_string_pretty(&reply).unwrap()),
+            Err(Error::Timeout) => return Ok(()),
+            Err(err) => return Err(err.to_string())
+        }
+    }
+}
+
+/// Writes `contents` to the file at `path`, or to stdout if there's none.
+fn output(path: Option<String>, contents: &[u8]) -> Result<(), String> {
+    match path {
+        Some(path) => write_file(&path, contents).map_err(|err| err.to_string()),
+        None => io::stdout().write_all(contents).map_err(|err| err.to_string())
+    }
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let mut client = connect(&options)?;
+
+    let command = options.command.as_str();
+    match (command, options.args.get(0).cloned()) {
+        ("send", Some(path)) => {
+            let contents = read_file(&path).map_err(|err| err.to_string())?;
+            let mut sent = 0;
+            for step in steps_in(&contents) {
+                match step {
+                    Step::Send(body) => {
+                        client.send_raw(&body).map_err(|err| err.to_string())?;
+                        sent += 1;
+                    }
+                    // The recorded session ended here, which the renderer
+                    // answers by clearing its display list, so the rest is
+                    // sent over a new connection.
+                    Step::Close => client = connect(&options)?
+                }
+            }
+            eprintln!("Sent {} message(s)", sent);
+            print_replies(&client)
+        }
+        ("raw", Some(body)) => {
+            client.send_raw(&body).map_err(|err| err.to_string())?;
+            print_replies(&client)
+        }
+        ("dump", path) | ("svg", path) | ("stats", path) => {
+            let reply = client
+                .request(&Message::new().request(command, Value::Null))
+                .map_err(|err| err.to_string())?;
+            let contents = match reply[command] {
+                Value::String(ref document) => document.clone(),
+                ref value => serde_json::to_string_pretty(value).unwrap()
+            };
+            output(path, contents.as_bytes())
+        }
+        ("screenshot", Some(path)) => {
+            let reply = client
+                .request(&Message::new().request(command, Value::Null))
+                .map_err(|err| err.to_string())?;
+            let png = reply[command]["png"]
+                .as_str()
+                .and_then(|data| base64::decode(data).ok())
+                .ok_or_else(|| format!("Unexpected reply: {}", reply))?;
+            output(Some(path), &png)
+        }
+        _ => Err(format!("Unknown command or missing argument for `{}`", command))
+    }
+}
+
+fn main() {
+    let options = parse_options().unwrap_or_else(|message| {
+        eprintln!("{}\n\n{}", message, USAGE);
+        process::exit(1);
+    });
+
+    if let Err(message) = run(options) {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
+}
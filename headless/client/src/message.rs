@@ -0,0 +1,606 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+use serde_json::{Map, Value};
+
+use rsx_primitives::compare::export as Diff;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub left: u32,
+    pub top: u32,
+    pub width: u32,
+    pub height: u32
+}
+
+impl Bounds {
+    pub fn new(left: u32, top: u32, width: u32, height: u32) -> Self {
+        Bounds {
+            left,
+            top,
+            width,
+            height
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "position": { "left": self.left, "top": self.top },
+            "size": { "width": self.width, "height": self.height }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rgba {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+    pub alpha: u8
+}
+
+impl Rgba {
+    pub fn new(red: u8, green: u8, blue: u8, alpha: u8) -> Self {
+        Rgba {
+            red,
+            green,
+            blue,
+            alpha
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({ "red": self.red, "green": self.green, "blue": self.blue, "alpha": self.alpha })
+    }
+}
+
+/// A glyph index into a font, and the advance in pixels to the next glyph.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Glyph {
+    pub index: u32,
+    pub advance: f32
+}
+
+impl Glyph {
+    pub fn new(index: u32, advance: f32) -> Self {
+        Glyph { index, advance }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({ "index": self.index, "advance": self.advance })
+    }
+}
+
+/// A span of text drawn with its own font and, optionally, its own color
+/// instead of the item's. Runs which come with their glyphs are drawn as is,
+/// the others are shaped by the renderer, which keeps their glyphs until the
+/// text changes. Runs which ask for a face are drawn with the font of their
+/// font's family that matches it best.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextRun {
+    pub text: String,
+    pub font_key: u64,
+    pub font_instance_key: u64,
+    pub color: Option<Rgba>,
+    pub glyphs: Option<Vec<Glyph>>,
+    pub decorations: Vec<TextDecoration>,
+    pub face: Option<FontDescriptor>
+}
+
+impl TextRun {
+    pub fn new(text: &str, font_key: u64, font_instance_key: u64) -> Self {
+        TextRun {
+            text: text.to_string(),
+            font_key,
+            font_instance_key,
+            color: None,
+            glyphs: None,
+            decorations: vec![],
+            face: None
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let mut shaped = json!({ "font_key": self.font_key, "font_instance_key": self.font_instance_key });
+        if let Some(color) = self.color {
+            shaped["color"] = color.to_json();
+        }
+        if let Some(ref glyphs) = self.glyphs {
+            shaped["glyphs"] = Value::Array(glyphs.iter().map(Glyph::to_json).collect());
+        }
+        if !self.decorations.is_empty() {
+            shaped["decorations"] = Value::Array(self.decorations.iter().map(TextDecoration::to_json).collect());
+        }
+        if let Some(face) = self.face {
+            face.write_json(&mut shaped);
+        }
+        shaped
+    }
+}
+
+/// A line drawn along a run, in the run's color and at the thickness the
+/// font suggests unless given otherwise.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextDecoration {
+    pub line: DecorationLine,
+    pub color: Option<Rgba>,
+    pub thickness: Option<f32>,
+    pub style: DecorationStyle
+}
+
+impl TextDecoration {
+    pub fn new(line: DecorationLine) -> Self {
+        TextDecoration {
+            line,
+            color: None,
+            thickness: None,
+            style: DecorationStyle::Solid
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let mut decoration = json!({ "line": self.line.to_json(), "style": self.style.to_json() });
+        if let Some(color) = self.color {
+            decoration["color"] = color.to_json();
+        }
+        if let Some(thickness) = self.thickness {
+            decoration["thickness"] = json!(thickness);
+        }
+        decoration
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecorationLine {
+    Underline,
+    Overline,
+    LineThrough
+}
+
+impl DecorationLine {
+    fn to_json(&self) -> Value {
+        match self {
+            &DecorationLine::Underline => json!("underline"),
+            &DecorationLine::Overline => json!("overline"),
+            &DecorationLine::LineThrough => json!("line-through")
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecorationStyle {
+    Solid,
+    Double,
+    Dotted,
+    Dashed,
+    Wavy
+}
+
+impl DecorationStyle {
+    fn to_json(&self) -> Value {
+        match self {
+            &DecorationStyle::Solid => json!("solid"),
+            &DecorationStyle::Double => json!("double"),
+            &DecorationStyle::Dotted => json!("dotted"),
+            &DecorationStyle::Dashed => json!("dashed"),
+            &DecorationStyle::Wavy => json!("wavy")
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right
+}
+
+impl Default for TextAlign {
+    fn default() -> Self {
+        TextAlign::Left
+    }
+}
+
+impl TextAlign {
+    fn to_json(&self) -> Value {
+        match self {
+            &TextAlign::Left => json!("left"),
+            &TextAlign::Center => json!("center"),
+            &TextAlign::Right => json!("right")
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextDirection {
+    Ltr,
+    Rtl,
+    Auto
+}
+
+impl Default for TextDirection {
+    fn default() -> Self {
+        TextDirection::Ltr
+    }
+}
+
+impl TextDirection {
+    fn to_json(&self) -> Value {
+        match self {
+            &TextDirection::Ltr => json!("ltr"),
+            &TextDirection::Rtl => json!("rtl"),
+            &TextDirection::Auto => json!("auto")
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextOrientation {
+    Horizontal,
+    Vertical
+}
+
+impl Default for TextOrientation {
+    fn default() -> Self {
+        TextOrientation::Horizontal
+    }
+}
+
+impl TextOrientation {
+    fn to_json(&self) -> Value {
+        match self {
+            &TextOrientation::Horizontal => json!("horizontal"),
+            &TextOrientation::Vertical => json!("vertical")
+        }
+    }
+}
+
+/// What happens to text which doesn't fit its bounds. Every mode but
+/// `Visible` drops lines past the bottom, and the ellipsis modes shorten
+/// lines too long to fit with an ellipsis at their end or in their middle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextOverflow {
+    Visible,
+    Clip,
+    Ellipsis,
+    EllipsisMiddle
+}
+
+impl Default for TextOverflow {
+    fn default() -> Self {
+        TextOverflow::Visible
+    }
+}
+
+impl TextOverflow {
+    fn to_json(&self) -> Value {
+        match self {
+            &TextOverflow::Visible => json!("visible"),
+            &TextOverflow::Clip => json!("clip"),
+            &TextOverflow::Ellipsis => json!("ellipsis"),
+            &TextOverflow::EllipsisMiddle => json!("ellipsis-middle")
+        }
+    }
+}
+
+/// How text is broken into lines within its bounds. Newlines always start a
+/// new line, while `wrap` also breaks lines between words to fit the bounds'
+/// width. The line height is in pixels. Bidirectional text is ordered from
+/// the paragraph's direction, and vertical text is set in columns from right
+/// to left.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TextLayout {
+    pub wrap: bool,
+    pub line_height: Option<f32>,
+    pub align: TextAlign,
+    pub direction: TextDirection,
+    pub orientation: TextOrientation,
+    pub overflow: TextOverflow
+}
+
+impl TextLayout {
+    fn to_json(&self) -> Value {
+        let mut layout = json!({
+            "wrap": self.wrap,
+            "align": self.align.to_json(),
+            "direction": self.direction.to_json(),
+            "orientation": self.orientation.to_json(),
+            "overflow": self.overflow.to_json()
+        });
+        if let Some(line_height) = self.line_height {
+            layout["line_height"] = json!(line_height);
+        }
+        layout
+    }
+}
+
+/// The weight, style and stretch of a font face, or those a text run wants.
+/// Weights go from 1 to 1000, with 400 being normal and 700 bold, and
+/// stretch is a percentage of the normal width.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FontDescriptor {
+    pub weight: u16,
+    pub style: FontStyle,
+    pub stretch: f32
+}
+
+impl Default for FontDescriptor {
+    fn default() -> Self {
+        FontDescriptor {
+            weight: 400,
+            style: FontStyle::Normal,
+            stretch: 100.0
+        }
+    }
+}
+
+impl FontDescriptor {
+    fn write_json(&self, value: &mut Value) {
+        value["weight"] = json!(self.weight);
+        value["style"] = self.style.to_json();
+        value["stretch"] = json!(self.stretch);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FontStyle {
+    Normal,
+    Italic,
+    Oblique
+}
+
+impl FontStyle {
+    fn to_json(&self) -> Value {
+        match self {
+            &FontStyle::Normal => json!("normal"),
+            &FontStyle::Italic => json!("italic"),
+            &FontStyle::Oblique => json!("oblique")
+        }
+    }
+}
+
+/// Fonts are added inline as data URIs, from a file on the renderer's side,
+/// or by the family name of a font installed there. A descriptor overrides
+/// what the font says about its face, and picks which face of an installed
+/// family is added.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResourceUpdate {
+    AddFont { key: u64, data_uri: String, descriptor: Option<FontDescriptor> },
+    AddFontFile { key: u64, path: String, descriptor: Option<FontDescriptor> },
+    AddSystemFont { key: u64, family: String, descriptor: Option<FontDescriptor> },
+    AddFontInstance { key: u64, instance_key: u64, size: u32 }
+}
+
+impl ResourceUpdate {
+    fn to_json(&self) -> Value {
+        let (mut font, descriptor) = match self {
+            &ResourceUpdate::AddFont { key, ref data_uri, descriptor } => (json!({ "key": key, "data_uri": data_uri }), descriptor),
+            &ResourceUpdate::AddFontFile { key, ref path, descriptor } => (json!({ "key": key, "path": path }), descriptor),
+            &ResourceUpdate::AddSystemFont { key, ref family, descriptor } => (json!({ "key": key, "family": family }), descriptor),
+            &ResourceUpdate::AddFontInstance { key, instance_key, size } => {
+                return json!({
+                    "AddFontInstance": { "key": key, "instance_key": instance_key, "size": size }
+                })
+            }
+        };
+        if let Some(descriptor) = descriptor {
+            descriptor.write_json(&mut font);
+        }
+        json!({ "AddFont": font })
+    }
+}
+
+/// A single change to an existing display item, as carried by an `UpdateSelf` diff.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ItemChange {
+    Text(String),
+    Left(u32),
+    Top(u32),
+    Width(u32),
+    Height(u32)
+}
+
+impl ItemChange {
+    fn to_json(&self) -> Value {
+        match self {
+            &ItemChange::Text(ref text) => keyed(Diff::TEXT_UPDATE_KEY, keyed(Diff::TEXT_UPDATE_CHANGE_CONTENT_KEY, json!(text))),
+            &ItemChange::Left(left) => keyed(Diff::BOUNDS_UPDATE_KEY, keyed(Diff::BOUNDS_UPDATE_CHANGE_X_KEY, json!(left))),
+            &ItemChange::Top(top) => keyed(Diff::BOUNDS_UPDATE_KEY, keyed(Diff::BOUNDS_UPDATE_CHANGE_Y_KEY, json!(top))),
+            &ItemChange::Width(width) => keyed(Diff::BOUNDS_UPDATE_KEY, keyed(Diff::BOUNDS_UPDATE_CHANGE_WIDTH_KEY, json!(width))),
+            &ItemChange::Height(height) => keyed(Diff::BOUNDS_UPDATE_KEY, keyed(Diff::BOUNDS_UPDATE_CHANGE_HEIGHT_KEY, json!(height)))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DisplayListDiff {
+    AddRect {
+        bounds: Bounds,
+        color: Rgba
+    },
+    AddBorder {
+        bounds: Bounds,
+        colors: [Rgba; 4]
+    },
+    AddText {
+        bounds: Bounds,
+        color: Rgba,
+        text: String,
+        font_key: u64,
+        font_instance_key: u64
+    },
+    AddTextRuns {
+        bounds: Bounds,
+        color: Rgba,
+        runs: Vec<TextRun>,
+        layout: TextLayout
+    },
+    UpdateSelf {
+        index: usize,
+        changes: Vec<ItemChange>
+    }
+}
+
+impl DisplayListDiff {
+    fn to_json(&self) -> Value {
+        match self {
+            &DisplayListDiff::AddRect { ref bounds, ref color } => keyed(
+                Diff::ADD_RECT_KEY,
+                json!({
+                    "bounds": bounds.to_json(),
+                    "display": { "color": color.to_json() }
+                })
+            ),
+            &DisplayListDiff::AddBorder { ref bounds, ref colors } => keyed(
+                Diff::ADD_BORDER_KEY,
+                json!({
+                    "bounds": bounds.to_json(),
+                    "display": { "colors": colors.iter().map(Rgba::to_json).collect::<Vec<_>>() }
+                })
+            ),
+            &DisplayListDiff::AddText {
+                ref bounds,
+                ref color,
+                ref text,
+                font_key,
+                font_instance_key
+            } => keyed(
+                Diff::ADD_TEXT_KEY,
+                json!({
+                    "bounds": bounds.to_json(),
+                    "display": {
+                        "color": color.to_json(),
+                        "source_text": [{ "Owned": text }],
+                        "shaped_text": [{ "font_key": font_key, "font_instance_key": font_instance_key }]
+                    }
+                })
+            ),
+            &DisplayListDiff::AddTextRuns {
+                ref bounds,
+                ref color,
+                ref runs,
+                ref layout
+            } => keyed(
+                Diff::ADD_TEXT_KEY,
+                json!({
+                    "bounds": bounds.to_json(),
+                    "display": {
+                        "color": color.to_json(),
+                        "source_text": runs.iter().map(|run| json!({ "Owned": run.text })).collect::<Vec<_>>(),
+                        "shaped_text": runs.iter().map(TextRun::to_json).collect::<Vec<_>>(),
+                        "layout": layout.to_json()
+                    }
+                })
+            ),
+            &DisplayListDiff::UpdateSelf { index, ref changes } => keyed(
+                Diff::UPDATE_SELF_KEY,
+                json!([index, changes.iter().map(ItemChange::to_json).collect::<Vec<_>>()])
+            )
+        }
+    }
+}
+
+/// A single protocol message. Every part is optional, and they're applied by
+/// the renderer in the order: clear, position, size, resources, fallback,
+/// render.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Message {
+    clear: bool,
+    position: Option<(i32, i32)>,
+    size: Option<(u32, u32)>,
+    resources: Vec<ResourceUpdate>,
+    fallback: Option<Vec<u64>>,
+    render: Vec<DisplayListDiff>,
+    requests: Vec<(String, Value)>
+}
+
+impl Message {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn clear(mut self) -> Self {
+        self.clear = true;
+        self
+    }
+
+    pub fn position(mut self, x: i32, y: i32) -> Self {
+        self.position = Some((x, y));
+        self
+    }
+
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.size = Some((width, height));
+        self
+    }
+
+    pub fn resource(mut self, update: ResourceUpdate) -> Self {
+        self.resources.push(update);
+        self
+    }
+
+    /// Sets the keys of the fonts to draw characters missing from a run's
+    /// font with, in order, for the rest of the session. They're tried
+    /// before the renderer's own fallback fonts.
+    pub fn fallback(mut self, font_keys: Vec<u64>) -> Self {
+        self.fallback = Some(font_keys);
+        self
+    }
+
+    pub fn diff(mut self, diff: DisplayListDiff) -> Self {
+        self.render.push(diff);
+        self
+    }
+
+    /// Adds a request the renderer replies to, such as `dump` or `svg`.
+    pub fn request(mut self, key: &str, options: Value) -> Self {
+        self.requests.push((key.to_string(), options));
+        self
+    }
+
+    pub fn expects_reply(&self) -> bool {
+        !self.requests.is_empty()
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut message = Map::new();
+        if self.clear {
+            message.insert("clear".to_string(), json!(true));
+        }
+        if let Some((x, y)) = self.position {
+            message.insert("position".to_string(), json!([x, y]));
+        }
+        if let Some((width, height)) = self.size {
+            message.insert("size".to_string(), json!([width, height]));
+        }
+        if !self.resources.is_empty() {
+            let resources = self.resources.iter().map(ResourceUpdate::to_json).collect();
+            message.insert("resources".to_string(), Value::Array(resources));
+        }
+        if let Some(ref font_keys) = self.fallback {
+            message.insert("fallback".to_string(), json!(font_keys));
+        }
+        if !self.render.is_empty() {
+            let render = self.render.iter().map(DisplayListDiff::to_json).collect();
+            message.insert("render".to_string(), Value::Array(render));
+        }
+        for &(ref key, ref options) in &self.requests {
+            message.insert(key.clone(), options.clone());
+        }
+        Value::Object(message)
+    }
+}
+
+fn keyed(key: &str, value: Value) -> Value {
+    let mut map = Map::new();
+    map.insert(key.to_string(), value);
+    Value::Object(map)
+}
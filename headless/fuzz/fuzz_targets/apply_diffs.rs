@@ -0,0 +1,31 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+//! Applies a sequence of display list diffs to the same display list, one
+//! `render` array per line, the way they'd arrive over a session. Updates
+//! only do something once earlier lines have added items to update.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate renderer_process;
+extern crate serde_json;
+
+use renderer_process::protocol::apply_render;
+
+fuzz_target!(|data: &[u8]| {
+    let mut display_list = vec![];
+    for line in data.split(|&byte| byte == b'\n') {
+        if let Ok(render) = serde_json::from_slice(line) {
+            let _ = apply_render(&mut display_list, render);
+        }
+    }
+});
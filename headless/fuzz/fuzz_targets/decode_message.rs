@@ -0,0 +1,38 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+//! Handles a single protocol message, as received over the websocket: once
+//! through `receive_message`, then again through the server's `dispatch`,
+//! so it's also applied on top of itself and any error is turned into a
+//! reply. The renderer only speaks JSON text messages, so inputs that aren't
+//! UTF-8 are skipped. Closing the connection afterwards clears the state for
+//! the next input. Requests that write files (`dump`, `svg`, `screenshot`)
+//! are rejected, since no `--dump-dir` is set, and so are fonts added from
+//! files, since no `--font-dir` is.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate renderer_process;
+
+use std::str;
+
+use renderer_process::protocol::receive_message;
+use renderer_process::server::dispatch;
+use renderer_process::trace::TraceEvent;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(body) = str::from_utf8(data) {
+        let _ = receive_message(body);
+        dispatch(&TraceEvent::Message(body.to_string()));
+    }
+    dispatch(&TraceEvent::Close);
+});
@@ -0,0 +1,27 @@
+#!/bin/sh
+# Seeds the fuzzing corpora from the sessions the benchmarks replay, which are
+# shaped like the dbmonster-react and counter-ridiculous benchmarks:
+#
+#   ./seed-corpus.sh
+#
+# Traces recorded with `renderer-process --record` can be added on top:
+#
+#   ./seed-corpus.sh dbmonster.trace counter-ridiculous.trace
+#
+# Every message becomes a `decode_message` input, and the `render` arrays of
+# each session are split into `apply_diffs` inputs of a few messages each.
+
+set -e
+
+cd "$(dirname "$0")"
+cargo run --quiet --bin seed_corpus
+
+for trace in "$@"; do
+    name=$(basename "$trace" .trace)
+
+    jq -r '.event.Message // empty' "$trace" | split -l 1 -a 5 - "corpus/decode_message/$name-"
+    jq -c '.event.Message // empty | fromjson | .render // empty' "$trace" | split -l 8 -a 5 - "corpus/apply_diffs/$name-"
+done
+
+echo "decode_message: $(ls corpus/decode_message | wc -l) inputs"
+echo "apply_diffs: $(ls corpus/apply_diffs | wc -l) inputs"
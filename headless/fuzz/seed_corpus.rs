@@ -0,0 +1,55 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+//! Seeds the fuzzing corpora from the sessions the benchmarks replay, which
+//! are shaped like the dbmonster-react and counter-ridiculous benchmarks.
+//! Every message becomes a `decode_message` input, and the `render` arrays
+//! of each session are split into `apply_diffs` inputs of a few messages
+//! each.
+
+extern crate renderer_client;
+extern crate renderer_process;
+extern crate serde_json;
+
+#[path = "../benches/sessions/mod.rs"]
+mod sessions;
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+const MESSAGES_PER_DIFFS_INPUT: usize = 8;
+
+fn main() {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("corpus");
+    let decode_message = corpus.join("decode_message");
+    let apply_diffs = corpus.join("apply_diffs");
+    fs::create_dir_all(&decode_message).unwrap();
+    fs::create_dir_all(&apply_diffs).unwrap();
+
+    for (name, messages) in sessions::sessions() {
+        for (i, body) in messages.iter().enumerate() {
+            let mut file = File::create(decode_message.join(format!("{}-{:05}", name, i))).unwrap();
+            file.write_all(body.as_bytes()).unwrap();
+        }
+
+        let renders = messages
+            .iter()
+            .filter_map(|body| serde_json::from_str::<serde_json::Value>(body).unwrap().get("render").map(|render| render.to_string()))
+            .collect::<Vec<_>>();
+        for (i, chunk) in renders.chunks(MESSAGES_PER_DIFFS_INPUT).enumerate() {
+            let mut file = File::create(apply_diffs.join(format!("{}-{:05}", name, i))).unwrap();
+            for render in chunk {
+                writeln!(file, "{}", render).unwrap();
+            }
+        }
+    }
+}
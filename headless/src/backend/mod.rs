@@ -0,0 +1,260 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+mod recording;
+mod software;
+mod webrender;
+
+pub use self::recording::{RecordedItem, RecordingBackend};
+pub use self::software::SoftwareBackend;
+pub use self::webrender::WebRenderBackend;
+
+use std::collections::HashMap;
+
+use rsx_primitives::rsx_layout::types::LayoutBoundingClientRect;
+use rsx_primitives::rsx_stylesheet::types::{BorderStyle, Color};
+
+use decoration::{self, LineMetrics};
+use display_list::{RemoteDisplayItem, RemoteGlyph, RemoteTextItem, TextOrientation, TextOverflow};
+use font_matching::{self, FontDescriptor};
+use sfnt::Face;
+use shaping::ShapingCache;
+use text_layout::{self, ShapedRun};
+use text_runs::{ResolvedRun, RunCache};
+use util::saturating_u32;
+
+/// A font as resolved on the renderer side, from the keys a text item refers to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextFont<'a> {
+    pub family_name: &'a str,
+    pub size: u32
+}
+
+/// An output target the remote display list can be walked into.
+pub trait TRenderBackend {
+    fn push_rect(&mut self, rect: LayoutBoundingClientRect, color: Color);
+
+    fn push_border(&mut self, rect: LayoutBoundingClientRect, widths: [u32; 4], colors: [Color; 4], styles: [BorderStyle; 4]);
+
+    /// Pushes text in logical order, all of it in one direction, which `rtl`
+    /// tells.
+    fn push_text(&mut self, rect: LayoutBoundingClientRect, color: Color, font: TextFont, text: &str, rtl: bool);
+
+    /// Pushes text that's already shaped. `text` is what the glyphs were
+    /// shaped from, for backends which can't draw glyphs by index. Both are
+    /// in logical order, so right to left glyphs are laid out back to front.
+    fn push_glyphs(&mut self, rect: LayoutBoundingClientRect, color: Color, font: TextFont, text: &str, glyphs: &[RemoteGlyph], rtl: bool);
+
+    /// Shapes text the client didn't shape with the backend's own shaper.
+    /// The text is all in one direction, and the glyphs come back in logical
+    /// order. `face` is the font as parsed on this side, when it could be.
+    /// Backends without a shaper return `None`, and the text is pushed as is
+    /// instead.
+    fn shape_text(&mut self, _font: TextFont, _face: Option<&Face>, _text: &str, _rtl: bool) -> Option<Vec<RemoteGlyph>> {
+        None
+    }
+}
+
+/// The renderer's font tables, which text items' font keys are resolved
+/// through, and the fallback chain for characters their fonts don't have.
+#[derive(Clone, Copy)]
+pub struct Fonts<'a> {
+    pub family_names: &'a HashMap<u64, String>,
+    pub sizes: &'a HashMap<u64, u32>,
+    pub faces: &'a HashMap<u64, Face>,
+    pub descriptors: &'a HashMap<u64, FontDescriptor>,
+    pub fallback: &'a [u64]
+}
+
+impl<'a> Fonts<'a> {
+    pub fn resolve(&self, font_key: u64, font_instance_key: u64) -> Option<TextFont<'a>> {
+        let family_name = self.family_names.get(&font_key)?;
+        let size = *self.sizes.get(&font_instance_key)?;
+        Some(TextFont { family_name, size })
+    }
+
+    /// The key of the font in the same family as `font_key` which best
+    /// matches `wanted`, or `font_key` itself when no particular face was
+    /// asked for. Fonts registered without descriptors count as regular.
+    pub fn match_face(&self, font_key: u64, wanted: Option<&FontDescriptor>) -> u64 {
+        let (wanted, family_name) = match (wanted, self.family_names.get(&font_key)) {
+            (Some(wanted), Some(family_name)) => (wanted, family_name),
+            _ => return font_key
+        };
+        let mut keys = self.family_names
+            .iter()
+            .filter(|&(&key, name)| key != font_key && name == family_name)
+            .map(|(&key, _)| key)
+            .collect::<Vec<_>>();
+        // The requested font wins ties, then whichever was registered first.
+        keys.sort();
+        keys.insert(0, font_key);
+        let candidates = keys.iter()
+            .map(|&key| (key, self.descriptors.get(&key).cloned().unwrap_or_default()))
+            .collect::<Vec<_>>();
+        font_matching::best_match(&candidates, wanted).unwrap_or(font_key)
+    }
+}
+
+/// Font tables owned rather than borrowed from the renderer's state, for
+/// building display lists away from the runtime.
+#[derive(Default)]
+pub struct FontTables {
+    pub family_names: HashMap<u64, String>,
+    pub sizes: HashMap<u64, u32>,
+    pub faces: HashMap<u64, Face>,
+    pub descriptors: HashMap<u64, FontDescriptor>,
+    pub fallback: Vec<u64>
+}
+
+impl FontTables {
+    /// Adds a font in `family_name`, parsed as `face` when it could be.
+    pub fn font(mut self, font_key: u64, family_name: &str, face: Option<Face>) -> Self {
+        self.family_names.insert(font_key, family_name.to_string());
+        if let Some(face) = face {
+            self.faces.insert(font_key, face);
+        }
+        self
+    }
+
+    pub fn instance(mut self, font_instance_key: u64, size: u32) -> Self {
+        self.sizes.insert(font_instance_key, size);
+        self
+    }
+
+    pub fn fonts(&self) -> Fonts {
+        Fonts {
+            family_names: &self.family_names,
+            sizes: &self.sizes,
+            faces: &self.faces,
+            descriptors: &self.descriptors,
+            fallback: &self.fallback
+        }
+    }
+}
+
+/// Translates every item in `display_list` into calls on `backend`, resolving
+/// the client's font keys through the renderer's font tables. Text runs are
+/// resolved into the pieces they're drawn in through `runs`, and those the
+/// client didn't shape are shaped through `shaping`.
+pub fn build<B>(backend: &mut B, display_list: &[RemoteDisplayItem], fonts: Fonts, runs: &mut RunCache, shaping: &mut ShapingCache)
+where
+    B: TRenderBackend
+{
+    for (index, display_item) in display_list.iter().enumerate() {
+        match display_item {
+            &RemoteDisplayItem::Rect(ref rect_display_item) => {
+                backend.push_rect(rect_display_item.rect, rect_display_item.color);
+            }
+            &RemoteDisplayItem::Border(ref border_display_item) => {
+                backend.push_border(
+                    border_display_item.rect,
+                    border_display_item.widths,
+                    border_display_item.colors,
+                    border_display_item.styles
+                );
+            }
+            &RemoteDisplayItem::Text(ref text_display_item) => {
+                let runs = runs.resolve(index, text_display_item, &fonts);
+                build_text(backend, text_display_item, runs, fonts, shaping);
+            }
+            _ => unimplemented!()
+        }
+    }
+}
+
+fn build_text<B>(backend: &mut B, item: &RemoteTextItem, resolved: &[ResolvedRun], fonts: Fonts, shaping: &mut ShapingCache)
+where
+    B: TRenderBackend
+{
+    // Shaping on this side is only a fallback for clients which don't send
+    // glyphs. It's left to the backend's shaper and goes through the cache,
+    // since the same runs come back frame after frame. Everything is shaped
+    // up front so that all of the item's runs can borrow their glyphs from
+    // the cache at once, along with the ellipsis lines that don't fit might
+    // be cut with.
+    let ellipsize = match item.layout.overflow {
+        TextOverflow::Ellipsis | TextOverflow::EllipsisMiddle => true,
+        TextOverflow::Visible | TextOverflow::Clip => false
+    };
+    // Runs come split into the pieces they're drawn in, each in a single
+    // font and direction, so right to left text is shaped as such and only
+    // the pieces get reordered.
+    let split_runs = resolved
+        .iter()
+        .map(|resolved| (resolved.run(item), resolved.rtl))
+        .collect::<Vec<_>>();
+    shaping.start_pass();
+    for &(run, rtl) in &split_runs {
+        if let Some(font) = fonts.resolve(run.font_key, run.font_instance_key) {
+            let face = fonts.faces.get(&run.font_key);
+            if run.glyphs.is_none() {
+                let text = item.run_text(run);
+                shaping.shape(run.font_key, run.font_instance_key, rtl, text, || backend.shape_text(font, face, text, rtl));
+            }
+            if ellipsize {
+                let text = text_layout::ELLIPSIS;
+                shaping.shape(run.font_key, run.font_instance_key, false, text, || backend.shape_text(font, face, text, false));
+            }
+        }
+    }
+
+    let shaping: &ShapingCache = shaping;
+    let runs = split_runs
+        .iter()
+        .filter_map(|&(run, rtl)| {
+            // The font hasn't been registered yet, or never will be. Either
+            // way there's nothing to draw the text with.
+            let font = fonts.resolve(run.font_key, run.font_instance_key)?;
+            let glyphs = match run.glyphs {
+                Some(ref glyphs) => Some(&glyphs[..]),
+                None => shaping.get(run.font_key, run.font_instance_key, rtl, item.run_text(run))
+            };
+            let ellipsis = if ellipsize {
+                shaping.get(run.font_key, run.font_instance_key, false, text_layout::ELLIPSIS)
+            } else {
+                None
+            };
+            Some(ShapedRun {
+                run,
+                font,
+                face: fonts.faces.get(&run.font_key),
+                glyphs,
+                ellipsis
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let rect = item.rect;
+    let vertical = item.layout.orientation == TextOrientation::Vertical;
+    for line in text_layout::lay_out(item, &runs) {
+        for fragment in &line.fragments {
+            let fragment_rect = LayoutBoundingClientRect::new(
+                rect.position.left.saturating_add(saturating_u32(fragment.x.round())),
+                rect.position.top.saturating_add(saturating_u32(fragment.y.round())),
+                saturating_u32(fragment.width.ceil()),
+                saturating_u32(fragment.height.ceil())
+            );
+            let color = fragment.run.color;
+            let metrics = LineMetrics::new(fragment.face, fragment.font.size);
+            for decoration in fragment.run.decorations.iter().filter(|decoration| !decoration::is_over_text(decoration)) {
+                decoration::build(backend, decoration, decoration.color.unwrap_or(color), &metrics, fragment_rect, rect, vertical);
+            }
+            match fragment.glyphs {
+                Some(glyphs) => backend.push_glyphs(fragment_rect, color, fragment.font, fragment.text, glyphs, fragment.rtl),
+                None => backend.push_text(fragment_rect, color, fragment.font, fragment.text, fragment.rtl)
+            }
+            for decoration in fragment.run.decorations.iter().filter(|decoration| decoration::is_over_text(decoration)) {
+                decoration::build(backend, decoration, decoration.color.unwrap_or(color), &metrics, fragment_rect, rect, vertical);
+            }
+        }
+    }
+}
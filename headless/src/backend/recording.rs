@@ -0,0 +1,114 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+use rsx_primitives::rsx_layout::types::LayoutBoundingClientRect;
+use rsx_primitives::rsx_stylesheet::types::{BorderStyle, Color};
+
+use backend::{TRenderBackend, TextFont};
+use display_list::RemoteGlyph;
+use sfnt::Face;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordedItem {
+    Rect {
+        rect: LayoutBoundingClientRect,
+        color: Color
+    },
+    Border {
+        rect: LayoutBoundingClientRect,
+        widths: [u32; 4],
+        colors: [Color; 4],
+        styles: [BorderStyle; 4]
+    },
+    Text {
+        rect: LayoutBoundingClientRect,
+        color: Color,
+        family_name: String,
+        size: u32,
+        text: String,
+        rtl: bool
+    },
+    Glyphs {
+        rect: LayoutBoundingClientRect,
+        color: Color,
+        family_name: String,
+        size: u32,
+        glyphs: Vec<RemoteGlyph>,
+        rtl: bool
+    }
+}
+
+/// A backend which only remembers what was pushed into it, in order, so that
+/// the display list translation can be inspected without a GL context.
+#[derive(Debug, Default)]
+pub struct RecordingBackend {
+    pub items: Vec<RecordedItem>
+}
+
+impl RecordingBackend {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl TRenderBackend for RecordingBackend {
+    fn push_rect(&mut self, rect: LayoutBoundingClientRect, color: Color) {
+        self.items.push(RecordedItem::Rect { rect, color });
+    }
+
+    fn push_border(&mut self, rect: LayoutBoundingClientRect, widths: [u32; 4], colors: [Color; 4], styles: [BorderStyle; 4]) {
+        self.items.push(RecordedItem::Border {
+            rect,
+            widths,
+            colors,
+            styles
+        });
+    }
+
+    fn push_text(&mut self, rect: LayoutBoundingClientRect, color: Color, font: TextFont, text: &str, rtl: bool) {
+        self.items.push(RecordedItem::Text {
+            rect,
+            color,
+            family_name: font.family_name.to_string(),
+            size: font.size,
+            text: text.to_string(),
+            rtl
+        });
+    }
+
+    fn push_glyphs(&mut self, rect: LayoutBoundingClientRect, color: Color, font: TextFont, _: &str, glyphs: &[RemoteGlyph], rtl: bool) {
+        self.items.push(RecordedItem::Glyphs {
+            rect,
+            color,
+            family_name: font.family_name.to_string(),
+            size: font.size,
+            glyphs: glyphs.to_vec(),
+            rtl
+        });
+    }
+
+    /// Stands in for a font backend's shaper with one glyph per character,
+    /// from the face's character map, so that text in fonts parsed on this
+    /// side comes out shaped and the rest is left as text.
+    fn shape_text(&mut self, font: TextFont, face: Option<&Face>, text: &str, _: bool) -> Option<Vec<RemoteGlyph>> {
+        let face = face?;
+        let glyphs = text.chars()
+            .map(|c| {
+                let index = face.glyph_index(c).unwrap_or(0);
+                RemoteGlyph {
+                    index: index as u32,
+                    advance: face.scale(face.glyph_advance(index) as i32, font.size)
+                }
+            })
+            .collect();
+        Some(glyphs)
+    }
+}
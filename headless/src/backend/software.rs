@@ -0,0 +1,169 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+use std::io;
+
+use png::{self, HasParameters};
+
+use rsx_primitives::rsx_layout::types::LayoutBoundingClientRect;
+use rsx_primitives::rsx_stylesheet::types::{BorderStyle, Color};
+
+use backend::{TRenderBackend, TextFont};
+use display_list::RemoteGlyph;
+use util::color_components;
+
+// Rough average glyph proportions, relative to the font size, used to draw
+// text as placeholder blocks since there's no glyph rasterizer on this path.
+const GLYPH_ADVANCE_RATIO: f32 = 0.5;
+const GLYPH_HEIGHT_RATIO: f32 = 0.7;
+
+/// The largest frame drawn, in either dimension. Bigger frames are cropped
+/// to it rather than allocating whatever a client's bounds add up to.
+pub const MAX_SIZE: u32 = 8192;
+
+/// Rasterizes into an RGBA8 pixel buffer on the CPU, as an approximation of
+/// what WebRender would draw. Rects and borders are drawn exactly (with
+/// border styles approximated as solid), while text is "greeked" into one
+/// block per visible character.
+pub struct SoftwareBackend {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>
+}
+
+impl SoftwareBackend {
+    pub fn new(width: u32, height: u32) -> Self {
+        let (width, height) = (width.min(MAX_SIZE), height.min(MAX_SIZE));
+        SoftwareBackend {
+            width,
+            height,
+            pixels: vec![255; width as usize * height as usize * 4]
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn into_pixels(self) -> Vec<u8> {
+        self.pixels
+    }
+
+    pub fn encode_png(&self) -> io::Result<Vec<u8>> {
+        let mut data = vec![];
+        {
+            let mut encoder = png::Encoder::new(&mut data, self.width, self.height);
+            encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+            let mut writer = encoder.write_header()?;
+            writer.write_image_data(&self.pixels)?;
+        }
+        Ok(data)
+    }
+
+    fn fill(&mut self, left: f32, top: f32, right: f32, bottom: f32, color: [u8; 4]) {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let clamp_x = |v: f32| v.round().max(0.0).min(width) as u32;
+        let clamp_y = |v: f32| v.round().max(0.0).min(height) as u32;
+        let (x0, x1, y0, y1) = (clamp_x(left), clamp_x(right), clamp_y(top), clamp_y(bottom));
+        let alpha = color[3] as u32;
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let offset = (y as usize * self.width as usize + x as usize) * 4;
+                let pixel = &mut self.pixels[offset..offset + 4];
+                for c in 0..3 {
+                    pixel[c] = ((color[c] as u32 * alpha + pixel[c] as u32 * (255 - alpha)) / 255) as u8;
+                }
+                pixel[3] = (alpha + pixel[3] as u32 * (255 - alpha) / 255) as u8;
+            }
+        }
+    }
+}
+
+impl TRenderBackend for SoftwareBackend {
+    fn push_rect(&mut self, rect: LayoutBoundingClientRect, color: Color) {
+        let (left, top) = (rect.position.left as f32, rect.position.top as f32);
+        let (right, bottom) = (left + rect.size.width as f32, top + rect.size.height as f32);
+        self.fill(left, top, right, bottom, color_components(&color));
+    }
+
+    fn push_border(&mut self, rect: LayoutBoundingClientRect, widths: [u32; 4], colors: [Color; 4], styles: [BorderStyle; 4]) {
+        let (left, top) = (rect.position.left as f32, rect.position.top as f32);
+        let (right, bottom) = (left + rect.size.width as f32, top + rect.size.height as f32);
+
+        // Sides are in CSS order: top, right, bottom, left.
+        for side in 0..4 {
+            match styles[side] {
+                BorderStyle::None | BorderStyle::Hidden => continue,
+                _ => {}
+            }
+            let width = widths[side] as f32;
+            let color = color_components(&colors[side]);
+            match side {
+                0 => self.fill(left, top, right, top + width, color),
+                1 => self.fill(right - width, top, right, bottom, color),
+                2 => self.fill(left, bottom - width, right, bottom, color),
+                _ => self.fill(left, top, left + width, bottom, color)
+            }
+        }
+    }
+
+    fn push_text(&mut self, rect: LayoutBoundingClientRect, color: Color, font: TextFont, text: &str, rtl: bool) {
+        let advance = font.size as f32 * GLYPH_ADVANCE_RATIO;
+        let height = font.size as f32 * GLYPH_HEIGHT_RATIO;
+        let top = rect.position.top as f32 + (font.size as f32 - height) / 2.0;
+        let color = color_components(&color);
+
+        let mut chars = text.chars().collect::<Vec<_>>();
+        if rtl {
+            chars.reverse();
+        }
+        let mut x = rect.position.left as f32;
+        for c in chars {
+            if !c.is_whitespace() {
+                self.fill(x + advance * 0.1, top, x + advance * 0.9, top + height, color);
+            }
+            x += advance;
+        }
+    }
+
+    fn push_glyphs(&mut self, rect: LayoutBoundingClientRect, color: Color, font: TextFont, text: &str, glyphs: &[RemoteGlyph], rtl: bool) {
+        let height = font.size as f32 * GLYPH_HEIGHT_RATIO;
+        let top = rect.position.top as f32 + (font.size as f32 - height) / 2.0;
+        let color = color_components(&color);
+
+        // Glyphs don't map one to one to characters once there are ligatures,
+        // but it's close enough to tell which blocks should stay blank.
+        let mut chars = text.chars();
+        let mut glyphs = glyphs
+            .iter()
+            .map(|glyph| (glyph, chars.next().map_or(false, char::is_whitespace)))
+            .collect::<Vec<_>>();
+        if rtl {
+            glyphs.reverse();
+        }
+        let mut x = rect.position.left as f32;
+        for (glyph, blank) in glyphs {
+            if !blank {
+                self.fill(x + glyph.advance * 0.1, top, x + glyph.advance * 0.9, top + height, color);
+            }
+            x += glyph.advance;
+        }
+    }
+}
@@ -0,0 +1,147 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+use rsx_native_renderer::webrender::api::{BuiltDisplayList, GlyphInstance, LayoutPoint, LayoutSize, PipelineId};
+use rsx_primitives::build::types::DisplayListBuilder;
+use rsx_primitives::prelude::{ResourceGroup, ShapedText};
+use rsx_primitives::rsx_dom::types::DOMText;
+use rsx_primitives::rsx_layout::types::LayoutBoundingClientRect;
+use rsx_primitives::rsx_shared::traits::{TFontCache, TResourceGroup};
+use rsx_primitives::rsx_shared::types::GlyphStore;
+use rsx_primitives::rsx_stylesheet::types::{BorderStyle, Color};
+use rsx_primitives::traits::TDisplayListBuilder;
+
+use backend::{TRenderBackend, TextFont};
+use display_list::RemoteGlyph;
+use sfnt::Face;
+use text_layout::FALLBACK_ADVANCE_RATIO;
+
+pub struct WebRenderBackend<'a> {
+    builder: DisplayListBuilder,
+    resources: &'a mut ResourceGroup
+}
+
+impl<'a> WebRenderBackend<'a> {
+    pub fn new(pipeline_id: PipelineId, layout_size: LayoutSize, resources: &'a mut ResourceGroup) -> Self {
+        WebRenderBackend {
+            builder: DisplayListBuilder::new(pipeline_id, layout_size),
+            resources
+        }
+    }
+
+    pub fn serialize(mut self) -> BuiltDisplayList {
+        self.builder.serialize()
+    }
+}
+
+impl<'a> TRenderBackend for WebRenderBackend<'a> {
+    fn push_rect(&mut self, rect: LayoutBoundingClientRect, color: Color) {
+        TDisplayListBuilder::push_rect(&mut self.builder, rect, color);
+    }
+
+    fn push_border(&mut self, rect: LayoutBoundingClientRect, widths: [u32; 4], colors: [Color; 4], styles: [BorderStyle; 4]) {
+        TDisplayListBuilder::push_border(&mut self.builder, rect, widths, colors, styles);
+    }
+
+    fn push_text(&mut self, rect: LayoutBoundingClientRect, color: Color, font: TextFont, text: &str, _: bool) {
+        let font_instance = self.resources
+            .fonts()
+            .get_font_with_size(font.family_name, font.size)
+            .unwrap();
+
+        let glyph_store = self.resources
+            .fonts()
+            .shape_text_h(&font_instance, text)
+            .unwrap();
+
+        TDisplayListBuilder::push_text(
+            &mut self.builder,
+            rect,
+            color,
+            &ShapedText::from(glyph_store),
+            &DOMText::from("")
+        );
+    }
+
+    fn push_glyphs(&mut self, rect: LayoutBoundingClientRect, color: Color, font: TextFont, _: &str, glyphs: &[RemoteGlyph], rtl: bool) {
+        let font_instance = self.resources
+            .fonts()
+            .get_font_with_size(font.family_name, font.size)
+            .unwrap();
+
+        // Positions are relative to the text's origin, the same as the ones
+        // `shape_text_h` would have produced, and go left to right whichever
+        // way the text reads.
+        let mut x = 0.0;
+        let mut visual = glyphs.iter().collect::<Vec<_>>();
+        if rtl {
+            visual.reverse();
+        }
+        let glyph_instances = visual
+            .into_iter()
+            .map(|glyph| {
+                let instance = GlyphInstance {
+                    index: glyph.index,
+                    point: LayoutPoint::new(x, 0.0)
+                };
+                x += glyph.advance;
+                instance
+            })
+            .collect();
+
+        TDisplayListBuilder::push_text(
+            &mut self.builder,
+            rect,
+            color,
+            &ShapedText::from(GlyphStore::new(&font_instance, glyph_instances)),
+            &DOMText::from("")
+        );
+    }
+
+    fn shape_text(&mut self, font: TextFont, face: Option<&Face>, text: &str, rtl: bool) -> Option<Vec<RemoteGlyph>> {
+        let font_instance = self.resources
+            .fonts()
+            .get_font_with_size(font.family_name, font.size)?;
+
+        let glyph_store = self.resources
+            .fonts()
+            .shape_text_h(&font_instance, text)?;
+
+        // The shaper takes the direction from the text, and places glyphs
+        // left to right rather than advancing them, so each glyph's advance
+        // is the distance to the next one. The last glyph has nothing after
+        // it and takes its advance from the font's metrics.
+        let positions = glyph_store.glyphs
+            .iter()
+            .map(|glyph| glyph.point.x)
+            .chain(glyph_store.glyphs.last().map(|glyph| {
+                let advance = match face {
+                    Some(face) => face.scale(face.glyph_advance(glyph.index as u16) as i32, font.size),
+                    None => font.size as f32 * FALLBACK_ADVANCE_RATIO
+                };
+                glyph.point.x + advance
+            }))
+            .collect::<Vec<_>>();
+        let mut glyphs = glyph_store.glyphs
+            .iter()
+            .zip(positions.windows(2))
+            .map(|(glyph, x)| RemoteGlyph {
+                index: glyph.index,
+                advance: x[1] - x[0]
+            })
+            .collect::<Vec<_>>();
+        // Right to left glyphs come out in visual order.
+        if rtl {
+            glyphs.reverse();
+        }
+        Some(glyphs)
+    }
+}
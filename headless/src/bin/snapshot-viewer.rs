@@ -0,0 +1,143 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+//! Opens a display list snapshot written by the renderer process (see the
+//! `dump` message) and renders it without any client attached. Arrow keys
+//! step through the display items, outlining the selected one and logging
+//! it, `Home`/`End` jump to the first/last item and `Escape` clears the
+//! selection.
+
+#[macro_use]
+extern crate renderer_process;
+extern crate rsx_native_renderer;
+extern crate rsx_primitives;
+
+use std::env;
+use std::process;
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+
+use renderer_process::logging::Level;
+use renderer_process::runtime::Runtime;
+use renderer_process::snapshot::Snapshot;
+use renderer_process::state::{REMOTE_DISPLAY_LIST, SHOULD_REDRAW};
+use rsx_native_renderer::glutin::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use rsx_native_renderer::types::Runner;
+use rsx_native_renderer::webrender::api::{BuiltDisplayList, LayoutSize, PipelineId, RenderApi, ResourceUpdates};
+use rsx_primitives::prelude::{DOMTree, ResourceGroup};
+use rsx_primitives::rsx_shared::traits::{TRunner, TRuntime};
+
+struct Viewer {
+    runtime: Runtime
+}
+
+impl Viewer {
+    fn navigate(&mut self, key: VirtualKeyCode) {
+        let remote_display_list = REMOTE_DISPLAY_LIST.lock().unwrap();
+        let len = remote_display_list.len();
+        if len == 0 {
+            return;
+        }
+
+        let current = self.runtime.highlighted_item;
+        let next = match key {
+            VirtualKeyCode::Right | VirtualKeyCode::Down => Some(current.map_or(0, |i| (i + 1) % len)),
+            VirtualKeyCode::Left | VirtualKeyCode::Up => Some(current.map_or(len - 1, |i| (i + len - 1) % len)),
+            VirtualKeyCode::Home => Some(0),
+            VirtualKeyCode::End => Some(len - 1),
+            VirtualKeyCode::Escape => None,
+            _ => return
+        };
+
+        if let Some(i) = next {
+            log_event!(Level::Info, "viewer", "Highlighted item", index = i, count = len, item = remote_display_list[i]);
+        }
+
+        self.runtime.highlighted_item = next;
+        SHOULD_REDRAW.swap(true, Ordering::Relaxed);
+    }
+}
+
+impl TRuntime for Viewer {
+    type RootRendererAPI = Rc<RenderApi>;
+    type DOMResources = ResourceGroup;
+    type DOMTree = DOMTree;
+    type VirtualEventMetadata = (Event,);
+    type ReflowMetadata = (PipelineId, LayoutSize);
+    type BuiltDisplayList = BuiltDisplayList;
+    type ResourceUpdates = ResourceUpdates;
+
+    fn new<S, R>(api: &Self::RootRendererAPI, setup: S, render: R) -> Self
+    where
+        S: Fn(&mut Self::DOMResources),
+        R: Fn() -> Self::DOMTree
+    {
+        Viewer {
+            runtime: Runtime::new(api, setup, render)
+        }
+    }
+
+    fn should_set_window_position(&mut self) -> Option<(i32, i32)> {
+        self.runtime.should_set_window_position()
+    }
+
+    fn should_set_window_size(&mut self) -> Option<(u32, u32)> {
+        self.runtime.should_set_window_size()
+    }
+
+    fn should_redraw(&mut self) -> bool {
+        self.runtime.should_redraw()
+    }
+
+    fn handle_event(&mut self, (event,): Self::VirtualEventMetadata) -> bool {
+        if let &Event::WindowEvent {
+            event: WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(key),
+                    ..
+                },
+                ..
+            },
+            ..
+        } = &event
+        {
+            self.navigate(key);
+        }
+        self.runtime.handle_event((event,))
+    }
+
+    fn take_resource_updates(&mut self) -> Self::ResourceUpdates {
+        self.runtime.take_resource_updates()
+    }
+
+    fn generate_display_list(&mut self, metadata: Self::ReflowMetadata) -> Self::BuiltDisplayList {
+        self.runtime.generate_display_list(metadata)
+    }
+}
+
+const USAGE: &str = "Usage: snapshot-viewer <SNAPSHOT>";
+
+fn main() {
+    let path = env::args().nth(1).unwrap_or_else(|| {
+        eprintln!("{}", USAGE);
+        process::exit(1);
+    });
+
+    match Snapshot::open(&path) {
+        Ok(snapshot) => snapshot.restore(),
+        Err(err) => {
+            eprintln!("Couldn't open snapshot `{}`: {}\n\n{}", path, err, USAGE);
+            process::exit(1);
+        }
+    }
+    Runner::run(|api| Viewer::new(api, empty_setup!(), empty_render!()));
+}
@@ -0,0 +1,332 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+//! Draws underlines, overlines and strikethroughs along laid out text, as
+//! rects so that every backend can draw them.
+
+use std::f32::consts::PI;
+
+use rsx_primitives::rsx_layout::types::LayoutBoundingClientRect;
+use rsx_primitives::rsx_stylesheet::types::Color;
+
+use backend::TRenderBackend;
+use display_list::{DecorationLine, DecorationStyle, RemoteTextDecoration};
+use sfnt::Face;
+use util::saturating_u32;
+
+/// Thicker lines than this, in pixels, are drawn this thick.
+const MAX_THICKNESS: f32 = 64.0;
+
+/// Dotted, dashed and wavy lines are drawn in at most this many pieces, and
+/// cut short if they would take more.
+const MAX_STEPS: u32 = 4096;
+
+/// A font's vertical metrics in pixels at a given size. Positions are
+/// measured upwards from the baseline and are those of the top of the line,
+/// while the descent is measured downwards.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineMetrics {
+    pub ascent: f32,
+    pub descent: f32,
+    pub underline_position: f32,
+    pub underline_thickness: f32,
+    pub strikeout_position: f32,
+    pub strikeout_thickness: f32
+}
+
+impl LineMetrics {
+    /// The metrics of `face` at `size` pixels. Fonts that couldn't be parsed
+    /// get proportions typical of Latin fonts.
+    pub fn new(face: Option<&Face>, size: u32) -> Self {
+        match face {
+            Some(face) => {
+                let metrics = face.vertical_metrics();
+                let scale = |units: i16| face.scale(units as i32, size);
+                LineMetrics {
+                    ascent: scale(metrics.ascender),
+                    descent: -scale(metrics.descender),
+                    underline_position: scale(metrics.underline_position),
+                    underline_thickness: scale(metrics.underline_thickness),
+                    strikeout_position: scale(metrics.strikeout_position),
+                    strikeout_thickness: scale(metrics.strikeout_thickness)
+                }
+            }
+            None => {
+                let size = size as f32;
+                LineMetrics {
+                    ascent: size * 0.8,
+                    descent: size * 0.2,
+                    underline_position: -size * 0.1,
+                    underline_thickness: size / 20.0,
+                    strikeout_position: size * 0.25,
+                    strikeout_thickness: size / 20.0
+                }
+            }
+        }
+    }
+
+    /// How far down a line box `height` pixels tall the baseline is, with the
+    /// leading split evenly above and below the glyphs.
+    pub fn baseline(&self, height: f32) -> f32 {
+        (height - (self.ascent + self.descent)) / 2.0 + self.ascent
+    }
+}
+
+/// Line-throughs are drawn over the text, and other lines beneath it.
+pub fn is_over_text(decoration: &RemoteTextDecoration) -> bool {
+    decoration.line == DecorationLine::LineThrough
+}
+
+/// Draws `decoration` along text laid out in `rect`, which is a line box for
+/// horizontal text, or a single character's box for vertical text. Vertical
+/// text is underlined on its right and overlined on its left. Lines don't
+/// run past the edges of `bounds`, the box of the item the text is in.
+pub fn build<B>(
+    backend: &mut B,
+    decoration: &RemoteTextDecoration,
+    color: Color,
+    metrics: &LineMetrics,
+    rect: LayoutBoundingClientRect,
+    bounds: LayoutBoundingClientRect,
+    vertical: bool
+) where
+    B: TRenderBackend
+{
+    let (left, top) = (rect.position.left as f32, rect.position.top as f32);
+    let (width, height) = (rect.size.width as f32, rect.size.height as f32);
+
+    let default_thickness = match decoration.line {
+        DecorationLine::LineThrough => metrics.strikeout_thickness,
+        _ => metrics.underline_thickness
+    };
+    let thickness = decoration.thickness.unwrap_or(default_thickness).max(1.0).min(MAX_THICKNESS);
+
+    // Strokes are described along the text and across it, starting from
+    // where the line's edge nearest the text's top or left is.
+    let stroke = if vertical {
+        let cross = match decoration.line {
+            DecorationLine::Underline => left + width,
+            DecorationLine::Overline => left - thickness,
+            DecorationLine::LineThrough => left + (width - thickness) / 2.0
+        };
+        let (along, length) = clip(top, height, bounds.position.top as f32, bounds.size.height as f32);
+        Stroke {
+            along,
+            length,
+            cross,
+            thickness,
+            vertical
+        }
+    } else {
+        let baseline = top + metrics.baseline(height);
+        let position = match decoration.line {
+            DecorationLine::Underline => metrics.underline_position,
+            DecorationLine::Overline => metrics.ascent,
+            DecorationLine::LineThrough => metrics.strikeout_position
+        };
+        let (along, length) = clip(left, width, bounds.position.left as f32, bounds.size.width as f32);
+        Stroke {
+            along,
+            length,
+            cross: baseline - position,
+            thickness,
+            vertical
+        }
+    };
+
+    // The text is entirely outside the item.
+    if stroke.length == 0.0 {
+        return;
+    }
+
+    match decoration.style {
+        DecorationStyle::Solid => stroke.solid(backend, color),
+        DecorationStyle::Double => {
+            stroke.solid(backend, color);
+            Stroke {
+                cross: stroke.cross + thickness * 2.0,
+                ..stroke
+            }.solid(backend, color);
+        }
+        DecorationStyle::Dotted => stroke.dashed(backend, color, thickness, thickness),
+        DecorationStyle::Dashed => stroke.dashed(backend, color, thickness * 3.0, thickness * 2.0),
+        DecorationStyle::Wavy => stroke.wavy(backend, color)
+    }
+}
+
+/// The part of the span `length` long from `start` that's within the one
+/// `extent` long from `bounds_start`, as a start and a length.
+fn clip(start: f32, length: f32, bounds_start: f32, extent: f32) -> (f32, f32) {
+    let clipped_start = start.max(bounds_start);
+    let clipped_end = (start + length).min(bounds_start + extent);
+    (clipped_start, (clipped_end - clipped_start).max(0.0))
+}
+
+#[derive(Clone, Copy)]
+struct Stroke {
+    along: f32,
+    length: f32,
+    cross: f32,
+    thickness: f32,
+    vertical: bool
+}
+
+impl Stroke {
+    fn solid<B>(&self, backend: &mut B, color: Color)
+    where
+        B: TRenderBackend
+    {
+        self.push(backend, color, 0.0, self.length, 0.0);
+    }
+
+    fn dashed<B>(&self, backend: &mut B, color: Color, dash: f32, gap: f32)
+    where
+        B: TRenderBackend
+    {
+        for step in 0..self.steps(dash + gap) {
+            let offset = step as f32 * (dash + gap);
+            self.push(backend, color, offset, dash.min(self.length - offset), 0.0);
+        }
+    }
+
+    /// Approximates a wave with a square the line's thickness wide at every
+    /// step along it.
+    fn wavy<B>(&self, backend: &mut B, color: Color)
+    where
+        B: TRenderBackend
+    {
+        let amplitude = self.thickness;
+        let wavelength = self.thickness * 8.0;
+        for step in 0..self.steps(self.thickness / 2.0) {
+            let offset = step as f32 * self.thickness / 2.0;
+            let shift = amplitude * (offset / wavelength * 2.0 * PI).sin();
+            self.push(backend, color, offset, self.thickness.min(self.length - offset), shift + amplitude);
+        }
+    }
+
+    /// How many pieces `distance` apart are drawn along the line. Offsets
+    /// are worked out from the count rather than added up, as adding a small
+    /// distance to a large enough offset leaves it unchanged.
+    fn steps(&self, distance: f32) -> u32 {
+        saturating_u32((self.length / distance).ceil()).min(MAX_STEPS)
+    }
+
+    fn push<B>(&self, backend: &mut B, color: Color, offset: f32, length: f32, shift: f32)
+    where
+        B: TRenderBackend
+    {
+        let along = saturating_u32((self.along + offset).round());
+        let cross = saturating_u32((self.cross + shift).round());
+        let length = saturating_u32(length.round()).max(1);
+        let thickness = saturating_u32(self.thickness.round());
+        let rect = if self.vertical {
+            LayoutBoundingClientRect::new(cross, along, thickness, length)
+        } else {
+            LayoutBoundingClientRect::new(along, cross, length, thickness)
+        };
+        backend.push_rect(rect, color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use backend::{self, FontTables, RecordedItem, RecordingBackend};
+    use display_list::{RemoteDisplayItem, RemoteTextItem};
+    use shaping::ShapingCache;
+    use text_runs::RunCache;
+
+    use super::*;
+
+    fn decoration(style: DecorationStyle, thickness: f32) -> RemoteTextDecoration {
+        RemoteTextDecoration {
+            line: DecorationLine::Underline,
+            color: None,
+            thickness: Some(thickness),
+            style
+        }
+    }
+
+    fn rects(decoration: &RemoteTextDecoration, rect: LayoutBoundingClientRect, bounds: LayoutBoundingClientRect) -> Vec<LayoutBoundingClientRect> {
+        let mut backend = RecordingBackend::new();
+        let metrics = LineMetrics::new(None, 16);
+        build(&mut backend, decoration, Color::new([0, 0, 0, 255]), &metrics, rect, bounds, false);
+        backend
+            .items
+            .into_iter()
+            .map(|item| match item {
+                RecordedItem::Rect { rect, .. } => rect,
+                item => panic!("unexpected item {:?}", item)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lines_are_clipped_to_the_item() {
+        let bounds = LayoutBoundingClientRect::new(10, 0, 100, 20);
+        let rect = LayoutBoundingClientRect::new(60, 0, 100, 20);
+        let solid = rects(&decoration(DecorationStyle::Solid, 1.0), rect, bounds);
+        assert_eq!(solid.len(), 1);
+        assert_eq!((solid[0].position.left, solid[0].size.width), (60, 50));
+
+        let outside = LayoutBoundingClientRect::new(200, 0, 100, 20);
+        assert!(rects(&decoration(DecorationStyle::Solid, 1.0), outside, bounds).is_empty());
+    }
+
+    #[test]
+    fn long_and_thick_lines_are_bounded() {
+        let huge = LayoutBoundingClientRect::new(0, 0, u32::max_value(), 20);
+        for &style in &[DecorationStyle::Dotted, DecorationStyle::Dashed, DecorationStyle::Wavy] {
+            assert!(rects(&decoration(style, 1.0), huge, huge).len() as u32 <= MAX_STEPS);
+        }
+
+        let thick = rects(&decoration(DecorationStyle::Solid, 1e30), huge, huge);
+        assert_eq!(thick[0].size.height, MAX_THICKNESS as u32);
+    }
+
+    #[test]
+    fn decorations_are_drawn_around_the_text() {
+        let red = Color::new([255, 0, 0, 255]);
+        let blue = Color::new([0, 0, 255, 255]);
+        let line_through = RemoteTextDecoration {
+            line: DecorationLine::LineThrough,
+            color: Some(blue),
+            ..decoration(DecorationStyle::Solid, 3.0)
+        };
+        let mut item = RemoteTextItem::new(LayoutBoundingClientRect::new(10, 20, 100, 50), red, "abc", Default::default());
+        item.runs[0].decorations = vec![
+            RemoteTextDecoration {
+                thickness: None,
+                ..decoration(DecorationStyle::Solid, 1.0)
+            },
+            line_through,
+        ];
+        let fonts = FontTables::default().font(1, "Sans", None).instance(2, 16);
+        let display_list = [RemoteDisplayItem::Text(item)];
+        let mut recording = RecordingBackend::new();
+        backend::build(&mut recording, &display_list, fonts.fonts(), &mut RunCache::default(), &mut ShapingCache::default());
+
+        // Without font metrics, a 16 pixel font's baseline is 12.8 pixels
+        // below the top of its glyphs, which are centered in a 20 pixel tall
+        // line. The underline goes beneath the text and the line-through
+        // over it.
+        let rects = recording
+            .items
+            .into_iter()
+            .map(|item| match item {
+                RecordedItem::Rect { rect, color } => {
+                    Some(((rect.position.left, rect.position.top, rect.size.width, rect.size.height), color))
+                }
+                _ => None
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(rects, vec![Some(((10, 36, 24, 1), red)), None, Some(((10, 31, 24, 3), blue))]);
+    }
+}
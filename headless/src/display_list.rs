@@ -0,0 +1,280 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+use rsx_primitives::rsx_layout::types::LayoutBoundingClientRect;
+use rsx_primitives::rsx_stylesheet::types::{BorderStyle, Color};
+
+use font_matching::FontDescriptor;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum RemoteDisplayItem {
+    Rect(RemoteRectItem),
+    Border(RemoteBorderItem),
+    Image(RemoteImageItem),
+    Text(RemoteTextItem)
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RemoteRectItem {
+    pub rect: LayoutBoundingClientRect,
+    pub color: Color
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RemoteBorderItem {
+    pub rect: LayoutBoundingClientRect,
+    pub colors: [Color; 4],
+    pub styles: [BorderStyle; 4],
+    pub widths: [u32; 4]
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RemoteImageItem {
+    // TODO
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RemoteTextItem {
+    pub rect: LayoutBoundingClientRect,
+    pub color: Color,
+    pub text: String,
+    pub runs: Vec<RemoteTextRun>,
+    #[serde(default)]
+    pub layout: RemoteTextLayout
+}
+
+#[cfg(test)]
+impl RemoteTextItem {
+    /// Text in a single run, with font 1 at instance 2.
+    pub fn new(rect: LayoutBoundingClientRect, color: Color, text: &str, layout: RemoteTextLayout) -> Self {
+        RemoteTextItem {
+            rect,
+            color,
+            text: text.to_string(),
+            runs: vec![RemoteTextRun {
+                font_key: 1,
+                font_instance_key: 2,
+                start: 0,
+                end: text.len(),
+                color,
+                glyphs: None,
+                decorations: vec![],
+                face: None
+            }],
+            layout
+        }
+    }
+}
+
+/// How a text item's lines are laid out within its bounds. Explicit newlines
+/// always start a new line, while breaking lines between words to fit the
+/// item's width is opt in. Without a line height, lines are 1.2 times as
+/// tall as the largest font in the item. The direction is the base direction
+/// of its paragraphs, with `Auto` taking it from their first strong
+/// character. Text which doesn't fit is drawn past the item's bounds unless
+/// its overflow says otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct RemoteTextLayout {
+    pub wrap: bool,
+    pub line_height: Option<f32>,
+    pub align: TextAlign,
+    #[serde(default)]
+    pub direction: TextDirection,
+    #[serde(default)]
+    pub orientation: TextOrientation,
+    #[serde(default)]
+    pub overflow: TextOverflow
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right
+}
+
+impl Default for TextAlign {
+    fn default() -> Self {
+        TextAlign::Left
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TextDirection {
+    Ltr,
+    Rtl,
+    Auto
+}
+
+impl Default for TextDirection {
+    fn default() -> Self {
+        TextDirection::Ltr
+    }
+}
+
+/// Vertical text is set in columns from right to left, with characters
+/// upright, the way CJK text is.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TextOrientation {
+    Horizontal,
+    Vertical
+}
+
+impl Default for TextOrientation {
+    fn default() -> Self {
+        TextOrientation::Horizontal
+    }
+}
+
+/// What happens to lines too long for their item, and to lines past its
+/// bottom, which are dropped in every mode but `Visible`. Lines are cut
+/// between whole glyphs, so clipped text stops at the last one that fits.
+/// The ellipsis modes replace the end or the middle of a line with an
+/// ellipsis, and end the last line kept with one when lines were dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TextOverflow {
+    Visible,
+    Clip,
+    Ellipsis,
+    EllipsisMiddle
+}
+
+impl Default for TextOverflow {
+    fn default() -> Self {
+        TextOverflow::Visible
+    }
+}
+
+/// A span of a text item drawn with a single font and color. The range is
+/// in bytes into the item's text. Runs the client already shaped carry their
+/// glyphs, otherwise they're shaped on the renderer side. Runs which ask for
+/// a weight, style or stretch are drawn with the face of their font's family
+/// that matches it best.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RemoteTextRun {
+    pub font_key: u64,
+    pub font_instance_key: u64,
+    pub start: usize,
+    pub end: usize,
+    pub color: Color,
+    #[serde(default)]
+    pub glyphs: Option<Vec<RemoteGlyph>>,
+    #[serde(default)]
+    pub decorations: Vec<RemoteTextDecoration>,
+    #[serde(default)]
+    pub face: Option<FontDescriptor>
+}
+
+/// A line drawn along a run's text. Without a color of its own it takes the
+/// run's, and without a thickness it takes the font's underline or
+/// strikeout thickness.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RemoteTextDecoration {
+    pub line: DecorationLine,
+    pub color: Option<Color>,
+    pub thickness: Option<f32>,
+    pub style: DecorationStyle
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DecorationLine {
+    Underline,
+    Overline,
+    LineThrough
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DecorationStyle {
+    Solid,
+    Double,
+    Dotted,
+    Dashed,
+    Wavy
+}
+
+/// A glyph shaped by the client, as an index into the font and the advance
+/// in pixels to the next glyph's origin.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RemoteGlyph {
+    pub index: u32,
+    pub advance: f32
+}
+
+impl RemoteTextItem {
+    pub fn run_text(&self, run: &RemoteTextRun) -> &str {
+        self.text.get(run.start..run.end).unwrap_or("")
+    }
+
+    /// Replaces the text content. There's no telling which parts of the new
+    /// text the old runs map to, so it all takes the first run's font and
+    /// color, and has to be shaped again.
+    pub fn set_text(&mut self, text: String) {
+        self.runs.truncate(1);
+        if let Some(run) = self.runs.first_mut() {
+            run.start = 0;
+            run.end = text.len();
+            run.glyphs = None;
+        }
+        self.text = text;
+    }
+}
+
+impl RemoteDisplayItem {
+    pub fn rect(&self) -> Option<LayoutBoundingClientRect> {
+        match self {
+            &RemoteDisplayItem::Rect(ref item) => Some(item.rect),
+            &RemoteDisplayItem::Border(ref item) => Some(item.rect),
+            &RemoteDisplayItem::Text(ref item) => Some(item.rect),
+            &RemoteDisplayItem::Image(_) => None
+        }
+    }
+
+    pub fn rect_mut(&mut self) -> Option<&mut LayoutBoundingClientRect> {
+        match self {
+            &mut RemoteDisplayItem::Rect(ref mut item) => Some(&mut item.rect),
+            &mut RemoteDisplayItem::Border(ref mut item) => Some(&mut item.rect),
+            &mut RemoteDisplayItem::Text(ref mut item) => Some(&mut item.rect),
+            &mut RemoteDisplayItem::Image(_) => None
+        }
+    }
+
+    pub fn as_rect(&mut self) -> Option<&mut RemoteRectItem> {
+        if let &mut RemoteDisplayItem::Rect(ref mut item) = self {
+            Some(item)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_border(&mut self) -> Option<&mut RemoteBorderItem> {
+        if let &mut RemoteDisplayItem::Border(ref mut item) = self {
+            Some(item)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_image(&mut self) -> Option<&mut RemoteImageItem> {
+        if let &mut RemoteDisplayItem::Image(ref mut item) = self {
+            Some(item)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_text(&mut self) -> Option<&mut RemoteTextItem> {
+        if let &mut RemoteDisplayItem::Text(ref mut item) = self {
+            Some(item)
+        } else {
+            None
+        }
+    }
+}
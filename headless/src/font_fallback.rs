@@ -0,0 +1,220 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+//! Draws the characters a run's font doesn't have with the first font in
+//! the fallback chain that does, so that emoji, symbols and CJK text don't
+//! come out as missing glyph boxes. The chain is the session's, set by the
+//! client, followed by the global one the renderer was started with.
+
+use std::borrow::Cow;
+use std::ops::Range;
+use std::path::PathBuf;
+
+use unicode_bidi::{bidi_class, BidiClass};
+
+use backend::Fonts;
+use display_list::{RemoteDisplayItem, RemoteTextItem, RemoteTextRun};
+use resources::RemoteFontResource;
+use sfnt::Face;
+
+// Global fallback fonts are registered under keys counting down from here.
+// The range is reserved, so clients can't add fonts under these keys.
+const GLOBAL_KEYS_END: u64 = u64::max_value();
+const MAX_GLOBAL_FONTS: u64 = 1 << 16;
+
+/// Whether `key` is reserved for the global fallback fonts.
+pub fn is_global_key(key: u64) -> bool {
+    key > GLOBAL_KEYS_END - MAX_GLOBAL_FONTS
+}
+
+/// The fonts consulted, in order, for characters a run's font doesn't have.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FontFallback {
+    pub session: Vec<u64>,
+    pub global: Vec<u64>,
+    #[serde(skip)]
+    pub global_fonts: Vec<RemoteFontResource>
+}
+
+impl FontFallback {
+    /// Adds the font file at `path` to the end of the global chain, returning
+    /// the key it's registered under.
+    pub fn add_global(&mut self, path: PathBuf) -> u64 {
+        assert!((self.global.len() as u64) < MAX_GLOBAL_FONTS, "Too many global fallback fonts");
+        let key = GLOBAL_KEYS_END - self.global.len() as u64;
+        self.global.push(key);
+        let mut font = RemoteFontResource {
+            key,
+            data_uri: None,
+            path: None,
+            weight: None,
+            style: None,
+            stretch: None,
+            file_size: None
+        };
+        font.set_path(path);
+        self.global_fonts.push(font);
+        key
+    }
+
+    /// The session's fonts, then the global ones, without repeats.
+    pub fn chain(&self) -> Vec<u64> {
+        let mut chain = self.session.clone();
+        for &key in &self.global {
+            if !chain.contains(&key) {
+                chain.push(key);
+            }
+        }
+        chain
+    }
+}
+
+/// Which font a piece of a run's text is drawn with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Coverage {
+    Primary,
+    Fallback(u64),
+    Missing
+}
+
+/// A piece of a text item drawn with a font other than its run's, or with
+/// the run's font's missing glyph when no font in the chain has it. Offsets
+/// are in bytes into the item's text.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FallbackSpan {
+    pub item: usize,
+    pub run: usize,
+    pub start: usize,
+    pub end: usize,
+    pub font_key: Option<u64>
+}
+
+/// Splits `text` by which font draws each character: `primary` when it has
+/// the character, otherwise the first of `fallbacks` that does.
+pub fn split(text: &str, primary: &Face, fallbacks: &[(u64, &Face)]) -> Vec<(Range<usize>, Coverage)> {
+    let mut spans: Vec<(Range<usize>, Coverage)> = vec![];
+    for (offset, c) in text.char_indices() {
+        let end = offset + c.len_utf8();
+        // Marks, joiners and variation selectors stay with the character
+        // they modify, since they're shaped along with it.
+        let coverage = match spans.last() {
+            Some(&(_, last)) if joins_previous(c) => last,
+            _ => coverage(c, primary, fallbacks)
+        };
+        if spans.last().map_or(false, |&(_, last)| last == coverage) {
+            spans.last_mut().unwrap().0.end = end;
+        } else {
+            spans.push((offset..end, coverage));
+        }
+    }
+    spans
+}
+
+/// The pieces `run` is drawn in, each with the key of the font it's drawn
+/// with: the face matched for the run, or for characters it doesn't have,
+/// a fallback. Runs the client shaped, and runs whose fonts couldn't be
+/// parsed, are drawn as they are.
+pub fn split_run<'a>(item: &'a RemoteTextItem, run: &'a RemoteTextRun, fonts: &Fonts) -> Vec<Cow<'a, RemoteTextRun>> {
+    let font_key = fonts.match_face(run.font_key, run.face.as_ref());
+    let spans = coverage_of(item, run, font_key, fonts).unwrap_or_default();
+    if spans.iter().any(|&(_, coverage)| coverage != Coverage::Primary && coverage != Coverage::Missing) {
+        return spans
+            .into_iter()
+            .map(|(range, coverage)| {
+                Cow::Owned(RemoteTextRun {
+                    start: run.start + range.start,
+                    end: run.start + range.end,
+                    font_key: match coverage {
+                        Coverage::Fallback(key) => key,
+                        Coverage::Primary | Coverage::Missing => font_key
+                    },
+                    face: None,
+                    ..run.clone()
+                })
+            })
+            .collect();
+    }
+    if font_key == run.font_key {
+        vec![Cow::Borrowed(run)]
+    } else {
+        vec![Cow::Owned(RemoteTextRun {
+            font_key,
+            face: None,
+            ..run.clone()
+        })]
+    }
+}
+
+/// Every piece of text in `display_list` that isn't drawn with its run's
+/// font, for telling which fallback was picked.
+pub fn report(display_list: &[RemoteDisplayItem], fonts: &Fonts) -> Vec<FallbackSpan> {
+    let mut report = vec![];
+    for (index, display_item) in display_list.iter().enumerate() {
+        let item = match display_item {
+            &RemoteDisplayItem::Text(ref item) => item,
+            _ => continue
+        };
+        for (run_index, run) in item.runs.iter().enumerate() {
+            let font_key = fonts.match_face(run.font_key, run.face.as_ref());
+            for (range, coverage) in coverage_of(item, run, font_key, fonts).unwrap_or_default() {
+                let font_key = match coverage {
+                    Coverage::Primary => continue,
+                    Coverage::Fallback(key) => Some(key),
+                    Coverage::Missing => None
+                };
+                report.push(FallbackSpan {
+                    item: index,
+                    run: run_index,
+                    start: run.start + range.start,
+                    end: run.start + range.end,
+                    font_key
+                });
+            }
+        }
+    }
+    report
+}
+
+/// How `run`'s text splits between the fonts, or `None` when there's no
+/// telling or it's all in `font_key`'s face.
+fn coverage_of(item: &RemoteTextItem, run: &RemoteTextRun, font_key: u64, fonts: &Fonts) -> Option<Vec<(Range<usize>, Coverage)>> {
+    if run.glyphs.is_some() || fonts.fallback.is_empty() {
+        return None;
+    }
+    let face = fonts.faces.get(&font_key)?;
+    let fallbacks = fonts.fallback
+        .iter()
+        .filter(|&&key| key != font_key)
+        .filter_map(|&key| fonts.faces.get(&key).map(|face| (key, face)))
+        .collect::<Vec<_>>();
+    let spans = split(item.run_text(run), face, &fallbacks);
+    if spans.iter().all(|&(_, coverage)| coverage == Coverage::Primary) {
+        None
+    } else {
+        Some(spans)
+    }
+}
+
+fn coverage(c: char, primary: &Face, fallbacks: &[(u64, &Face)]) -> Coverage {
+    if primary.glyph_index(c).is_some() {
+        return Coverage::Primary;
+    }
+    fallbacks
+        .iter()
+        .find(|&&(_, face)| face.glyph_index(c).is_some())
+        .map_or(Coverage::Missing, |&(key, _)| Coverage::Fallback(key))
+}
+
+fn joins_previous(c: char) -> bool {
+    // Zero width (non-)joiners, variation selectors and emoji skin tones.
+    let modifiers = [('\u{200C}', '\u{200D}'), ('\u{FE00}', '\u{FE0F}'), ('\u{1F3FB}', '\u{1F3FF}'), ('\u{E0100}', '\u{E01EF}')];
+    modifiers.iter().any(|&(first, last)| c >= first && c <= last) || bidi_class(c) == BidiClass::NSM
+}
@@ -0,0 +1,136 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+//! Picks the face of a font family that best matches a wanted weight, style
+//! and stretch, following the CSS font matching algorithm.
+
+use std::cmp::Ordering;
+
+use sfnt::Face;
+
+// Stretch percentages of the OS/2 width classes, from ultra-condensed to
+// ultra-expanded.
+const WIDTH_CLASSES: [f32; 9] = [50.0, 62.5, 75.0, 87.5, 100.0, 112.5, 125.0, 150.0, 200.0];
+
+/// What a font face is like, or what's wanted of one. Weights go from 1 to
+/// 1000, with 400 being normal and 700 bold, and stretch is a percentage of
+/// the normal width.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FontDescriptor {
+    pub weight: u16,
+    pub style: FontStyle,
+    pub stretch: f32
+}
+
+impl Default for FontDescriptor {
+    fn default() -> Self {
+        FontDescriptor {
+            weight: 400,
+            style: FontStyle::Normal,
+            stretch: 100.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum FontStyle {
+    Normal,
+    Italic,
+    Oblique
+}
+
+impl FontDescriptor {
+    /// What a face says about itself.
+    pub fn from_face(face: &Face) -> Self {
+        let style = face.style();
+        let width_class = style.width_class.max(1).min(WIDTH_CLASSES.len() as u16);
+        FontDescriptor {
+            weight: style.weight_class.max(1).min(1000),
+            style: if style.oblique {
+                FontStyle::Oblique
+            } else if style.italic {
+                FontStyle::Italic
+            } else {
+                FontStyle::Normal
+            },
+            stretch: WIDTH_CLASSES[width_class as usize - 1]
+        }
+    }
+}
+
+/// The key of the candidate that best matches `wanted`. Stretch narrows the
+/// candidates down first, then style, then weight, and of candidates that
+/// match equally well the first one wins.
+pub fn best_match<K>(candidates: &[(K, FontDescriptor)], wanted: &FontDescriptor) -> Option<K>
+where
+    K: Copy
+{
+    let candidates = closest(candidates.iter().collect(), |descriptor| stretch_rank(wanted.stretch, descriptor.stretch));
+    let candidates = closest(candidates, |descriptor| (style_rank(wanted.style, descriptor.style), 0.0));
+    let candidates = closest(candidates, |descriptor| weight_rank(wanted.weight, descriptor.weight));
+    candidates.first().map(|&&(key, _)| key)
+}
+
+/// Keeps the candidates which rank best, where ranks are a tier, then a
+/// distance within the tier.
+fn closest<'a, K, F>(candidates: Vec<&'a (K, FontDescriptor)>, rank: F) -> Vec<&'a (K, FontDescriptor)>
+where
+    F: Fn(&FontDescriptor) -> (u8, f32)
+{
+    let best = candidates
+        .iter()
+        .map(|&&(_, ref descriptor)| rank(descriptor))
+        .min_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    candidates.into_iter().filter(|&&(_, ref descriptor)| Some(rank(descriptor)) == best).collect()
+}
+
+/// Condensed and normal widths prefer narrower faces, and expanded ones
+/// wider faces.
+fn stretch_rank(wanted: f32, stretch: f32) -> (u8, f32) {
+    let preferred = if wanted <= 100.0 { stretch <= wanted } else { stretch >= wanted };
+    (if preferred { 0 } else { 1 }, (stretch - wanted).abs())
+}
+
+fn style_rank(wanted: FontStyle, style: FontStyle) -> u8 {
+    let preference = match wanted {
+        FontStyle::Normal => [FontStyle::Normal, FontStyle::Oblique, FontStyle::Italic],
+        FontStyle::Italic => [FontStyle::Italic, FontStyle::Oblique, FontStyle::Normal],
+        FontStyle::Oblique => [FontStyle::Oblique, FontStyle::Italic, FontStyle::Normal]
+    };
+    preference.iter().position(|&preferred| preferred == style).unwrap_or(preference.len()) as u8
+}
+
+/// Normal weights look at slightly heavier faces up to 500 first, then
+/// lighter ones. Otherwise lighter weights look at lighter faces first, and
+/// heavier weights at heavier faces.
+fn weight_rank(wanted: u16, weight: u16) -> (u8, f32) {
+    let distance = (weight as f32 - wanted as f32).abs();
+    let tier = if wanted >= 400 && wanted <= 500 {
+        if weight >= wanted && weight <= 500 {
+            0
+        } else if weight < wanted {
+            1
+        } else {
+            2
+        }
+    } else if wanted < 400 {
+        if weight <= wanted {
+            0
+        } else {
+            1
+        }
+    } else if weight >= wanted {
+        0
+    } else {
+        1
+    };
+    (tier, distance)
+}
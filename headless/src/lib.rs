@@ -0,0 +1,49 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+extern crate base64;
+#[macro_use]
+extern crate lazy_static;
+extern crate png;
+extern crate rsx_native_renderer;
+extern crate rsx_primitives;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+extern crate unicode_bidi;
+extern crate ws;
+
+#[macro_use]
+pub mod macros;
+pub mod backend;
+pub mod decoration;
+pub mod display_list;
+pub mod font_fallback;
+pub mod font_matching;
+pub mod logging;
+pub mod metrics;
+pub mod overlay;
+pub mod protocol;
+pub mod resources;
+pub mod runtime;
+pub mod server;
+pub mod sfnt;
+pub mod shaping;
+pub mod snapshot;
+pub mod state;
+pub mod svg;
+pub mod system_fonts;
+pub mod text_layout;
+pub mod text_runs;
+pub mod trace;
+pub mod util;
@@ -0,0 +1,217 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use serde_json;
+
+/// Environment variables read by `init_from_env`. Command line options take
+/// precedence over them.
+pub const LEVEL_VARIABLE: &str = "RENDERER_LOG";
+pub const FILE_VARIABLE: &str = "RENDERER_LOG_FILE";
+
+lazy_static! {
+    static ref LOGGER: Mutex<Logger> = Mutex::new(Logger {
+        level: Level::Info,
+        file: None
+    });
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace
+}
+
+impl Level {
+    pub fn name(&self) -> &'static str {
+        match self {
+            &Level::Error => "error",
+            &Level::Warn => "warn",
+            &Level::Info => "info",
+            &Level::Debug => "debug",
+            &Level::Trace => "trace"
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            "trace" => Ok(Level::Trace),
+            _ => Err(format!("Unknown log level `{}`", s))
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Named values attached to a log event, see `log_event!`.
+#[derive(Debug, Default)]
+pub struct Fields(serde_json::Map<String, serde_json::Value>);
+
+impl Fields {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn insert<T>(&mut self, key: &str, value: &T)
+    where
+        T: Serialize
+    {
+        let value = serde_json::to_value(value).unwrap_or(serde_json::Value::Null);
+        self.0.insert(key.to_string(), value);
+    }
+}
+
+struct Logger {
+    level: Level,
+    file: Option<BufWriter<File>>
+}
+
+/// Only events at `level` or more severe are logged. Events are always
+/// printed to stderr, and are also written as JSON lines to the file at
+/// `path`, if any.
+pub fn init<P>(level: Level, path: Option<P>) -> io::Result<()>
+where
+    P: AsRef<Path>
+{
+    let file = match path {
+        Some(path) => Some(BufWriter::new(File::create(path)?)),
+        None => None
+    };
+    *LOGGER.lock().unwrap() = Logger { level, file };
+    Ok(())
+}
+
+/// Same as `init`, configured from the `RENDERER_LOG` and `RENDERER_LOG_FILE`
+/// environment variables, with `level` and `path` overriding them.
+pub fn init_from_env(level: Option<Level>, path: Option<&Path>) -> Result<(), String> {
+    let level = match (level, env_var(LEVEL_VARIABLE)) {
+        (Some(level), _) => level,
+        (None, Some(value)) => value.parse()?,
+        (None, None) => Level::Info
+    };
+    let path = path.map(|path| path.to_path_buf()).or_else(|| env_var(FILE_VARIABLE).map(Into::into));
+    init(level, path).map_err(|err| err.to_string())
+}
+
+pub fn enabled(level: Level) -> bool {
+    level <= LOGGER.lock().unwrap().level
+}
+
+pub fn log(level: Level, target: &str, message: &str, fields: Fields) {
+    let mut logger = LOGGER.lock().unwrap();
+    if level > logger.level {
+        return;
+    }
+
+    let mut line = format!("[{} {}] {}", level.name().to_uppercase(), target, message);
+    for (key, value) in &fields.0 {
+        match value {
+            &serde_json::Value::String(ref string) => line.push_str(&format!(" {}={}", key, string)),
+            value => line.push_str(&format!(" {}={}", key, value))
+        }
+    }
+    eprintln!("{}", line);
+
+    if let Some(ref mut file) = logger.file {
+        let mut entry = fields.0;
+        entry.insert("time".to_string(), json!(unix_millis()));
+        entry.insert("level".to_string(), json!(level.name()));
+        entry.insert("target".to_string(), json!(target));
+        entry.insert("message".to_string(), json!(message));
+
+        // Failing to log shouldn't take the renderer down with it.
+        let _ = write_entry(file, &entry);
+    }
+}
+
+fn write_entry(file: &mut BufWriter<File>, entry: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+    serde_json::to_writer(&mut *file, entry)?;
+    file.write_all(b"\n")?;
+    file.flush()
+}
+
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok().and_then(|value| if value.is_empty() { None } else { Some(value) })
+}
+
+fn unix_millis() -> u64 {
+    let duration = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    duration.as_secs() * 1000 + duration.subsec_nanos() as u64 / 1000000
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Read;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn levels_are_parsed() {
+        assert_eq!("warn".parse::<Level>(), Ok(Level::Warn));
+        assert_eq!("trace".parse::<Level>(), Ok(Level::Trace));
+        assert!("loud".parse::<Level>().is_err());
+        assert!(Level::Error < Level::Debug);
+    }
+
+    #[test]
+    fn events_are_filtered_by_level_and_written_as_json_lines() {
+        let path = env::temp_dir().join("renderer-process-log-test.jsonl");
+        init(Level::Warn, Some(&path)).unwrap();
+        assert!(enabled(Level::Error) && enabled(Level::Warn));
+        assert!(!enabled(Level::Info));
+        log_event!(Level::Info, "logging-test", "Not logged");
+        log_event!(Level::Warn, "logging-test", "Logged", key = 1, path = "a b", keys = vec![2, 3]);
+        log_event!(Level::Error, "logging-test", "Also logged");
+        init(Level::Info, None::<PathBuf>).unwrap();
+
+        let mut contents = String::new();
+        File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+        fs::remove_file(&path).unwrap();
+        // Other tests may log at the same time, so only this test's events
+        // are looked at.
+        let entries = contents
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .filter(|entry| entry["target"] == json!("logging-test"))
+            .collect::<Vec<_>>();
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0]["level"].clone(), entries[0]["message"].clone()), (json!("warn"), json!("Logged")));
+        assert_eq!((entries[0]["key"].clone(), entries[0]["path"].clone(), entries[0]["keys"].clone()), (json!(1), json!("a b"), json!([2, 3])));
+        assert!(entries[0]["time"].as_u64().unwrap() > 0);
+        assert_eq!((entries[1]["level"].clone(), entries[1]["message"].clone()), (json!("error"), json!("Also logged")));
+    }
+}
@@ -9,14 +9,35 @@ CONDITIONS OF ANY KIND, either express or implied. See the License for the
 specific language governing permissions and limitations under the License.
 */
 
+#[macro_export]
 macro_rules! empty_setup {
     () => {
         |_| ()
     }
 }
 
+#[macro_export]
 macro_rules! empty_render {
     () => {
         || Default::default()
     }
 }
+
+/// Logs an event with a level, a target naming the area it comes from, a
+/// message and any number of `key = value` fields, where values can be
+/// anything serializable:
+///
+/// ```ignore
+/// log_event!(Level::Info, "connection", "Client connected", peer = address);
+/// ```
+#[macro_export]
+macro_rules! log_event {
+    ($level:expr, $target:expr, $message:expr $(, $key:ident = $value:expr)*) => {
+        if $crate::logging::enabled($level) {
+            #[allow(unused_mut)]
+            let mut fields = $crate::logging::Fields::new();
+            $(fields.insert(stringify!($key), &$value);)*
+            $crate::logging::log($level, $target, &$message, fields);
+        }
+    };
+}
@@ -0,0 +1,266 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json;
+
+use logging::Level;
+
+use state::{FRAME_METRICS, SHAPING_CACHE};
+
+/// Enough buckets for anything up to about half an hour.
+const BUCKET_COUNT: usize = 32;
+
+/// The stages a frame goes through, from receiving a message to having it on
+/// screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    /// Parsing messages, including any resource data they carry.
+    Decode,
+    /// Applying display list diffs.
+    Diff,
+    /// Registering pending resources with the font and image caches.
+    ResourceUpload,
+    /// Translating the display list for WebRender.
+    Build,
+    /// Everything the runner does with a built display list before polling
+    /// again, which is mostly rendering and presenting it.
+    Present
+}
+
+pub const STAGES: &[Stage] = &[Stage::Decode, Stage::Diff, Stage::ResourceUpload, Stage::Build, Stage::Present];
+
+impl Stage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            &Stage::Decode => "decode",
+            &Stage::Diff => "diff",
+            &Stage::ResourceUpload => "resource_upload",
+            &Stage::Build => "build",
+            &Stage::Present => "present"
+        }
+    }
+
+    fn index(&self) -> usize {
+        STAGES.iter().position(|stage| stage == self).unwrap()
+    }
+}
+
+/// Durations bucketed by powers of two microseconds, so that percentiles
+/// are accurate to within a factor of two without keeping every sample.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    buckets: [u64; BUCKET_COUNT],
+    count: u64,
+    total: u64,
+    min: u64,
+    max: u64
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            buckets: [0; BUCKET_COUNT],
+            count: 0,
+            total: 0,
+            min: u64::max_value(),
+            max: 0
+        }
+    }
+}
+
+impl Histogram {
+    pub fn record(&mut self, duration: Duration) {
+        let micros = as_micros(duration);
+        let bucket = (64 - micros.leading_zeros() as usize).min(BUCKET_COUNT - 1);
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.total += micros;
+        self.min = self.min.min(micros);
+        self.max = self.max.max(micros);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn mean(&self) -> u64 {
+        if self.count == 0 {
+            0
+        } else {
+            self.total / self.count
+        }
+    }
+
+    /// The upper bound of the bucket holding the `percentile`th sample, in
+    /// microseconds, clamped to the largest sample seen.
+    pub fn percentile(&self, percentile: f64) -> u64 {
+        let rank = (self.count as f64 * percentile / 100.0).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, &count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return ((1u64 << bucket) - 1).min(self.max);
+            }
+        }
+        self.max
+    }
+
+    pub fn summary(&self) -> serde_json::Value {
+        if self.count == 0 {
+            return json!({ "count": 0 });
+        }
+        json!({
+            "count": self.count,
+            "mean_us": self.mean(),
+            "min_us": self.min,
+            "p50_us": self.percentile(50.0),
+            "p90_us": self.percentile(90.0),
+            "p99_us": self.percentile(99.0),
+            "max_us": self.max
+        })
+    }
+}
+
+/// Per-stage timings for every frame since the last reset.
+#[derive(Debug, Clone, Default)]
+pub struct FrameMetrics {
+    histograms: [Histogram; 5],
+    frames: u64,
+    last_built: Option<Instant>
+}
+
+impl FrameMetrics {
+    pub fn record(&mut self, stage: Stage, duration: Duration) {
+        self.histograms[stage.index()].record(duration);
+    }
+
+    pub fn histogram(&self, stage: Stage) -> &Histogram {
+        &self.histograms[stage.index()]
+    }
+
+    /// Marks the end of a frame's build stage. The time until the runner next
+    /// polls is then recorded as that frame's present stage.
+    pub fn frame_built(&mut self) {
+        self.frames += 1;
+        self.last_built = Some(Instant::now());
+    }
+
+    pub fn runner_polled(&mut self) {
+        if let Some(last_built) = self.last_built.take() {
+            self.record(Stage::Present, last_built.elapsed());
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Default::default();
+    }
+
+    pub fn summary(&self) -> serde_json::Value {
+        let mut summary = serde_json::Map::new();
+        summary.insert("frames".to_string(), json!(self.frames));
+        for stage in STAGES {
+            summary.insert(stage.name().to_string(), self.histogram(*stage).summary());
+        }
+        serde_json::Value::Object(summary)
+    }
+}
+
+/// Runs `f`, recording how long it took as part of `stage`.
+pub fn time<F, T>(stage: Stage, f: F) -> T
+where
+    F: FnOnce() -> T
+{
+    let start = Instant::now();
+    let result = f();
+    FRAME_METRICS.lock().unwrap().record(stage, start.elapsed());
+    result
+}
+
+/// Prints a summary of the frame metrics and the shaping cache every
+/// `interval`, for as long as the process runs.
+pub fn report_every(interval: Duration) {
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        let summary = FRAME_METRICS.lock().unwrap().summary();
+        let shaping = SHAPING_CACHE.lock().unwrap().summary();
+        log_event!(Level::Info, "stats", "Frame stats", stats = summary, shaping = shaping);
+    });
+}
+
+pub fn as_micros(duration: Duration) -> u64 {
+    duration.as_secs() * 1000000 + duration.subsec_nanos() as u64 / 1000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn micros(micros: u32) -> Duration {
+        Duration::new(0, micros * 1000)
+    }
+
+    #[test]
+    fn histograms_bucket_durations_by_powers_of_two() {
+        let mut histogram = Histogram::default();
+        assert_eq!(histogram.summary(), json!({ "count": 0 }));
+        assert_eq!((histogram.mean(), histogram.percentile(50.0)), (0, 0));
+
+        for _ in 0..90 {
+            histogram.record(micros(10));
+        }
+        for _ in 0..9 {
+            histogram.record(micros(100));
+        }
+        histogram.record(micros(1000));
+
+        // Percentiles are the upper bounds of their buckets, except that the
+        // last one can't be past the largest sample.
+        assert_eq!(histogram.count(), 100);
+        assert_eq!(
+            histogram.summary(),
+            json!({
+                "count": 100,
+                "mean_us": 28,
+                "min_us": 10,
+                "p50_us": 15,
+                "p90_us": 15,
+                "p99_us": 127,
+                "max_us": 1000
+            })
+        );
+        assert_eq!(histogram.percentile(100.0), 1000);
+    }
+
+    #[test]
+    fn frames_are_summarized_by_stage_until_reset() {
+        let mut frame_metrics = FrameMetrics::default();
+        frame_metrics.record(Stage::Build, micros(300));
+        frame_metrics.frame_built();
+        frame_metrics.runner_polled();
+        // Polling again without a new frame doesn't count as presenting.
+        frame_metrics.runner_polled();
+
+        let summary = frame_metrics.summary();
+        assert_eq!(summary["frames"], json!(1));
+        assert_eq!(summary["build"]["count"], json!(1));
+        assert_eq!(summary["build"]["mean_us"], json!(300));
+        assert_eq!(summary["present"]["count"], json!(1));
+        assert_eq!(summary["resource_upload"], json!({ "count": 0 }));
+
+        frame_metrics.reset();
+        let summary = frame_metrics.summary();
+        assert_eq!(summary["frames"], json!(0));
+        assert_eq!(summary["build"], json!({ "count": 0 }));
+    }
+}
@@ -0,0 +1,149 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+use renderer_process::logging::Level;
+use renderer_process::shaping;
+use renderer_process::trace::ReplayMode;
+
+const USAGE: &str = "\
+Usage: renderer-process [OPTIONS]
+
+Options:
+    --address <ADDR>        Address to listen on (default: 127.0.0.1:6767)
+    --record <FILE>         Record every received message to a trace file
+    --replay <FILE>         Replay a trace file instead of listening for clients
+    --replay-mode <MODE>    One of `realtime`, `fast` or `step` (default: realtime)
+    --export-svg <FILE>     Convert a display list snapshot to SVG and exit
+    --output <FILE>         Where to write exported files (default: stdout)
+    --dump-dir <DIR>        Let clients have dumps written to files in DIR, by relative path;
+                            otherwise they only get them back in replies. F12 dumps go
+                            here too
+    --stats-interval <SECS> Print frame timing stats every SECS seconds
+    --shaping-cache-entries <N>
+                            Most text runs to keep shaped (default: 16384)
+    --shaping-cache-bytes <N>
+                            Most memory to keep shaped text runs in (default: 8388608)
+    --font-dir <DIR>        Let clients add fonts from files in DIR by path; may be given several
+                            times
+    --fallback-font <FAMILY>
+                            Draw characters missing from the client's fonts with this installed
+                            font family; may be given several times, in order of preference
+    --overlay               Start with the debug overlay shown (toggle with F11)
+    --log-level <LEVEL>     One of `error`, `warn`, `info`, `debug` or `trace` (default: info,
+                            or $RENDERER_LOG)
+    --log-file <FILE>       Also write log events to FILE as JSON lines (default: $RENDERER_LOG_FILE)
+    --help                  Print this message";
+
+pub struct Options {
+    pub address: String,
+    pub record: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub replay_mode: ReplayMode,
+    pub export_svg: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub dump_dir: Option<PathBuf>,
+    pub stats_interval: Option<u64>,
+    pub shaping_cache_entries: usize,
+    pub shaping_cache_bytes: usize,
+    pub font_dirs: Vec<PathBuf>,
+    pub fallback_fonts: Vec<String>,
+    pub overlay: bool,
+    pub log_level: Option<Level>,
+    pub log_file: Option<PathBuf>
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            address: "127.0.0.1:6767".to_string(),
+            record: None,
+            replay: None,
+            replay_mode: ReplayMode::RealTime,
+            export_svg: None,
+            output: None,
+            dump_dir: None,
+            stats_interval: None,
+            shaping_cache_entries: shaping::DEFAULT_MAX_ENTRIES,
+            shaping_cache_bytes: shaping::DEFAULT_MAX_BYTES,
+            font_dirs: vec![],
+            fallback_fonts: vec![],
+            overlay: false,
+            log_level: None,
+            log_file: None
+        }
+    }
+}
+
+impl Options {
+    pub fn from_args() -> Options {
+        match Options::parse(env::args().skip(1)) {
+            Ok(options) => options,
+            Err(message) => exit_with_usage(&message)
+        }
+    }
+
+    pub fn parse<I>(args: I) -> Result<Options, String>
+    where
+        I: IntoIterator<Item = String>
+    {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--address" => options.address = take_value(&arg, args.next())?,
+                "--record" => options.record = Some(PathBuf::from(take_value(&arg, args.next())?)),
+                "--replay" => options.replay = Some(PathBuf::from(take_value(&arg, args.next())?)),
+                "--replay-mode" => options.replay_mode = take_value(&arg, args.next())?.parse()?,
+                "--export-svg" => options.export_svg = Some(PathBuf::from(take_value(&arg, args.next())?)),
+                "--output" => options.output = Some(PathBuf::from(take_value(&arg, args.next())?)),
+                "--dump-dir" => options.dump_dir = Some(PathBuf::from(take_value(&arg, args.next())?)),
+                "--stats-interval" => {
+                    let value = take_value(&arg, args.next())?;
+                    options.stats_interval = Some(value.parse().map_err(|_| format!("Invalid interval `{}`", value))?);
+                }
+                "--shaping-cache-entries" => options.shaping_cache_entries = take_count(&arg, args.next())?,
+                "--shaping-cache-bytes" => options.shaping_cache_bytes = take_count(&arg, args.next())?,
+                "--font-dir" => options.font_dirs.push(PathBuf::from(take_value(&arg, args.next())?)),
+                "--fallback-font" => options.fallback_fonts.push(take_value(&arg, args.next())?),
+                "--overlay" => options.overlay = true,
+                "--log-level" => options.log_level = Some(take_value(&arg, args.next())?.parse()?),
+                "--log-file" => options.log_file = Some(PathBuf::from(take_value(&arg, args.next())?)),
+                "--help" => {
+                    println!("{}", USAGE);
+                    process::exit(0);
+                }
+                _ => return Err(format!("Unknown argument `{}`", arg))
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// Prints `message` followed by the usage text, and exits with an error.
+pub fn exit_with_usage(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    process::exit(1);
+}
+
+fn take_value(arg: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("Missing value for `{}`", arg))
+}
+
+fn take_count(arg: &str, value: Option<String>) -> Result<usize, String> {
+    let value = take_value(arg, value)?;
+    value.parse().map_err(|_| format!("Invalid count `{}`", value))
+}
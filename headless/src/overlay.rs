@@ -0,0 +1,245 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use base64;
+
+use rsx_primitives::rsx_layout::types::LayoutBoundingClientRect;
+use rsx_primitives::rsx_stylesheet::types::{BorderStyle, Color};
+
+use backend::{TRenderBackend, TextFont};
+use display_list::RemoteDisplayItem;
+use metrics;
+use resources::RemoteResource;
+
+/// The overlay brings its own font, so that it works before, or without,
+/// the client adding any.
+pub const FONT_NAME: &str = "debug-overlay";
+pub const FONT_SIZE: u32 = 12;
+const FONT_DATA: &[u8] = include_bytes!("../../fonts/FreeSans.ttf");
+
+const GRAPH_FRAMES: usize = 120;
+const GRAPH_BAR_WIDTH: u32 = 2;
+const GRAPH_HEIGHT: u32 = 60;
+const GRAPH_MICROS_PER_PIXEL: u64 = 500;
+const TARGET_FRAME_MICROS: u64 = 16667;
+
+const PANEL_LEFT: u32 = 8;
+const PANEL_TOP: u32 = 8;
+const PANEL_PADDING: u32 = 6;
+const LINE_HEIGHT: u32 = 16;
+
+pub fn font_data_uri() -> String {
+    format!("data:font/ttf;base64,{}", base64::encode(FONT_DATA))
+}
+
+/// Frame statistics drawn on top of the display list: frame rate, a graph
+/// of recent frame times, item counts and resource memory, plus an outline
+/// around every item colored by its type.
+#[derive(Debug, Default)]
+pub struct Overlay {
+    frame_times: VecDeque<Duration>,
+    last_frame: Option<Instant>
+}
+
+impl Overlay {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Marks the start of a frame. Frames are counted whether or not the
+    /// overlay is visible, so the graph is already filled in when it's shown.
+    pub fn frame(&mut self) {
+        let now = Instant::now();
+        if let Some(last_frame) = self.last_frame {
+            if self.frame_times.len() == GRAPH_FRAMES {
+                self.frame_times.pop_front();
+            }
+            self.frame_times.push_back(now.duration_since(last_frame));
+        }
+        self.last_frame = Some(now);
+    }
+
+    pub fn fps(&self) -> f64 {
+        let total: u64 = self.frame_times.iter().map(|&time| metrics::as_micros(time)).sum();
+        if total == 0 {
+            0.0
+        } else {
+            self.frame_times.len() as f64 * 1000000.0 / total as f64
+        }
+    }
+
+    /// Pushes the overlay into `backend`. Text is only drawn when `font` is
+    /// available, everything else doesn't need one.
+    pub fn build<B>(&self, backend: &mut B, display_list: &[RemoteDisplayItem], resources: &[RemoteResource], font: Option<TextFont>)
+    where
+        B: TRenderBackend
+    {
+        let mut counts = [0; 4];
+        for item in display_list {
+            let (index, color) = match item {
+                &RemoteDisplayItem::Rect(_) => (0, Color::new([0, 128, 255, 255])),
+                &RemoteDisplayItem::Border(_) => (1, Color::new([0, 200, 0, 255])),
+                &RemoteDisplayItem::Text(_) => (2, Color::new([255, 128, 0, 255])),
+                &RemoteDisplayItem::Image(_) => (3, Color::new([192, 0, 255, 255]))
+            };
+            counts[index] += 1;
+            if let Some(rect) = item.rect() {
+                backend.push_border(rect, [1; 4], [color; 4], [BorderStyle::Solid; 4]);
+            }
+        }
+
+        let lines = [
+            format!("{:.1} fps", self.fps()),
+            format!(
+                "{} items: {} rects, {} borders, {} text, {} images",
+                display_list.len(),
+                counts[0],
+                counts[1],
+                counts[2],
+                counts[3]
+            ),
+            describe_resources(resources),
+        ];
+
+        let width = GRAPH_FRAMES as u32 * GRAPH_BAR_WIDTH;
+        let text_height = if font.is_some() { lines.len() as u32 * LINE_HEIGHT } else { 0 };
+        let panel = LayoutBoundingClientRect::new(
+            PANEL_LEFT,
+            PANEL_TOP,
+            width + PANEL_PADDING * 2,
+            text_height + GRAPH_HEIGHT + PANEL_PADDING * 2
+        );
+        backend.push_rect(panel, Color::new([0, 0, 0, 192]));
+
+        let left = PANEL_LEFT + PANEL_PADDING;
+        let mut top = PANEL_TOP + PANEL_PADDING;
+        if let Some(font) = font {
+            for line in &lines {
+                let rect = LayoutBoundingClientRect::new(left, top, width, LINE_HEIGHT);
+                backend.push_text(rect, Color::new([255, 255, 255, 255]), font, line, false);
+                top += LINE_HEIGHT;
+            }
+        }
+
+        // Newest frames on the right, bars colored by how many vsyncs they took.
+        let bottom = top + GRAPH_HEIGHT;
+        let offset = GRAPH_FRAMES - self.frame_times.len();
+        for (i, &time) in self.frame_times.iter().enumerate() {
+            let micros = metrics::as_micros(time);
+            let height = ((micros / GRAPH_MICROS_PER_PIXEL) as u32).max(1).min(GRAPH_HEIGHT);
+            let color = if micros <= TARGET_FRAME_MICROS {
+                Color::new([0, 200, 0, 255])
+            } else if micros <= TARGET_FRAME_MICROS * 2 {
+                Color::new([255, 200, 0, 255])
+            } else {
+                Color::new([255, 0, 0, 255])
+            };
+            let x = left + (offset + i) as u32 * GRAPH_BAR_WIDTH;
+            let rect = LayoutBoundingClientRect::new(x, bottom - height, GRAPH_BAR_WIDTH, height);
+            backend.push_rect(rect, color);
+        }
+
+        let target = (TARGET_FRAME_MICROS / GRAPH_MICROS_PER_PIXEL) as u32;
+        let rect = LayoutBoundingClientRect::new(left, bottom - target, width, 1);
+        backend.push_rect(rect, Color::new([255, 255, 255, 128]));
+    }
+}
+
+/// Summarizes registered resources. Fonts sent inline are held as base64
+/// data URIs, so the size reported is that of the decoded data, while fonts
+/// from local files count as big as their file was when they were added.
+fn describe_resources(resources: &[RemoteResource]) -> String {
+    let mut fonts = 0;
+    let mut font_instances = 0;
+    let mut bytes = 0;
+    for resource in resources {
+        match resource {
+            &RemoteResource::Font(ref font) => {
+                fonts += 1;
+                bytes += font.size();
+            }
+            &RemoteResource::FontInstance(_) => font_instances += 1,
+            _ => {}
+        }
+    }
+    format!("{} fonts, {} font instances, {} KB", fonts, font_instances, bytes / 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use serde_json;
+
+    use backend::{RecordedItem, RecordingBackend};
+    use display_list::RemoteRectItem;
+
+    use super::*;
+
+    fn texts(recording: &RecordingBackend) -> Vec<String> {
+        recording
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                &RecordedItem::Text { ref text, .. } => Some(text.clone()),
+                _ => None
+            })
+            .collect()
+    }
+
+    #[test]
+    fn items_are_outlined_and_counted() {
+        let display_list = vec![RemoteDisplayItem::Rect(RemoteRectItem {
+            rect: LayoutBoundingClientRect::new(10, 20, 100, 50),
+            color: Color::new([255, 0, 0, 255])
+        })];
+        // Fonts count as big as their decoded data.
+        let font = format!(r#"{{ "Font": {{ "key": 1, "data_uri": "data:font/ttf;base64,{}" }} }}"#, base64::encode(&[0u8; 2048][..]));
+        let font_instance = r#"{ "FontInstance": { "key": 1, "instance_key": 2, "size": 16 } }"#;
+        let resources = vec![serde_json::from_str(&font).unwrap(), serde_json::from_str(font_instance).unwrap()];
+        let font = TextFont {
+            family_name: "Overlay",
+            size: FONT_SIZE
+        };
+
+        let mut recording = RecordingBackend::new();
+        Overlay::new().build(&mut recording, &display_list, &resources, Some(font));
+        match recording.items[0] {
+            RecordedItem::Border { rect, .. } => assert_eq!((rect.position.left, rect.position.top, rect.size.width), (10, 20, 100)),
+            _ => panic!("Expected an outline")
+        }
+        assert_eq!(
+            texts(&recording),
+            vec![
+                "0.0 fps".to_string(),
+                "1 items: 1 rects, 0 borders, 0 text, 0 images".to_string(),
+                "1 fonts, 1 font instances, 2 KB".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn everything_but_text_is_drawn_without_a_font() {
+        let mut overlay = Overlay::new();
+        overlay.frame();
+        thread::sleep(Duration::from_millis(10));
+        overlay.frame();
+        assert!(overlay.fps() > 0.0 && overlay.fps() <= 100.0);
+
+        let mut recording = RecordingBackend::new();
+        overlay.build(&mut recording, &[], &[], None);
+        assert!(!recording.items.is_empty());
+        assert!(texts(&recording).is_empty());
+    }
+}
@@ -0,0 +1,859 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+use std::error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use std::result;
+use std::sync::atomic::Ordering;
+
+use base64;
+use serde_json;
+
+use rsx_primitives::compare::export as Diff;
+use rsx_primitives::rsx_layout::types::LayoutBoundingClientRect;
+use rsx_primitives::rsx_stylesheet::types::{BorderStyle, Color};
+
+use display_list::{
+    DecorationLine,
+    DecorationStyle,
+    RemoteBorderItem,
+    RemoteDisplayItem,
+    RemoteGlyph,
+    RemoteRectItem,
+    RemoteTextDecoration,
+    RemoteTextItem,
+    RemoteTextLayout,
+    RemoteTextRun,
+    TextAlign,
+    TextDirection,
+    TextOrientation,
+    TextOverflow
+};
+use font_fallback;
+use font_matching::{FontDescriptor, FontStyle};
+use logging::{self, Level};
+use metrics::{self, Stage};
+use resources::{RemoteFontInstanceResource, RemoteFontResource, RemoteResource};
+use snapshot::Snapshot;
+use state::{
+    DUMP_DIR,
+    FONT_DIRS,
+    FONT_FALLBACK,
+    FRAME_METRICS,
+    REGISTERED_RESOURCES,
+    REMOTE_DISPLAY_LIST,
+    REMOTE_RESOURCES,
+    SHAPING_CACHE,
+    SHOULD_REDRAW,
+    SHOULD_SET_WINDOW_POSITION,
+    SHOULD_SET_WINDOW_SIZE,
+    SYSTEM_FONTS,
+    TEXT_RUNS,
+    WINDOW_SIZE
+};
+use svg;
+
+const MESSAGE_KEYS: &[&str] = &["clear", "position", "size", "resources", "fallback", "render", "dump", "svg", "screenshot", "stats"];
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    Json(serde_json::Error),
+    Io(io::Error),
+    BinaryMessage,
+    InvalidUtf8,
+    UnknownKey(String),
+    UnknownUpdate(String),
+    UnknownFont(u64),
+    ReservedFontKey(u64),
+    UnknownFontFamily(String),
+    MissingField(&'static str),
+    InvalidField(&'static str),
+    IndexOutOfRange(usize),
+    UnexpectedItem(usize),
+    ForbiddenPath(String),
+    ForbiddenFontPath(String)
+}
+
+pub type Result<T> = result::Result<T, ProtocolError>;
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ProtocolError::Json(ref err) => write!(f, "Malformed message: {}", err),
+            &ProtocolError::Io(ref err) => write!(f, "I/O error: {}", err),
+            &ProtocolError::BinaryMessage => write!(f, "Messages have to be sent as text frames, not binary ones"),
+            &ProtocolError::InvalidUtf8 => write!(f, "Message isn't valid UTF-8"),
+            &ProtocolError::UnknownKey(ref key) => write!(f, "Unknown message key `{}`", key),
+            &ProtocolError::UnknownUpdate(ref update) => write!(f, "Unknown update `{}`", update),
+            &ProtocolError::UnknownFont(key) => write!(f, "No font was added with key {}", key),
+            &ProtocolError::ReservedFontKey(key) => write!(f, "Font key {} is reserved for the renderer's fallback fonts", key),
+            &ProtocolError::UnknownFontFamily(ref family) => write!(f, "No installed font has the family name `{}`", family),
+            &ProtocolError::MissingField(name) => write!(f, "Missing field `{}`", name),
+            &ProtocolError::InvalidField(name) => write!(f, "Invalid value for field `{}`", name),
+            &ProtocolError::IndexOutOfRange(index) => write!(f, "No display item at index {}", index),
+            &ProtocolError::UnexpectedItem(index) => write!(f, "Display item at index {} can't be updated this way", index),
+            &ProtocolError::ForbiddenPath(ref path) => {
+                write!(f, "Can't write to `{}`, only to relative paths in the renderer's --dump-dir", path)
+            }
+            &ProtocolError::ForbiddenFontPath(ref path) => {
+                write!(f, "Can't read fonts from `{}`, only from the renderer's --font-dir directories", path)
+            }
+        }
+    }
+}
+
+impl error::Error for ProtocolError {
+    fn description(&self) -> &str {
+        "protocol error"
+    }
+}
+
+impl From<serde_json::Error> for ProtocolError {
+    fn from(err: serde_json::Error) -> Self {
+        ProtocolError::Json(err)
+    }
+}
+
+impl From<io::Error> for ProtocolError {
+    fn from(err: io::Error) -> Self {
+        ProtocolError::Io(err)
+    }
+}
+
+/// Handles a single message, returning the reply to send back, if any. A
+/// message with an unknown key is rejected as a whole; otherwise its parts are
+/// applied in order until one of them fails.
+pub fn receive_message(body: &str) -> Result<Option<serde_json::Value>> {
+    let mut parsed: serde_json::Value = metrics::time(Stage::Decode, || serde_json::from_str(body))?;
+    let mut reply = serde_json::Map::new();
+
+    if let Some(key) = parsed
+        .as_object()
+        .ok_or(ProtocolError::InvalidField("message"))?
+        .keys()
+        .find(|key| !MESSAGE_KEYS.contains(&key.as_str()))
+    {
+        return Err(ProtocolError::UnknownKey(key.clone()));
+    }
+
+    if logging::enabled(Level::Debug) {
+        let keys: Vec<&String> = parsed.as_object().unwrap().keys().collect();
+        log_event!(Level::Debug, "message", "Received message", bytes = body.len(), keys = keys);
+    }
+
+    if let Some(_) = parsed.get("clear") {
+        receive_clear();
+    }
+    if let Some(message) = parsed.get_mut("position") {
+        receive_position(message.take())?;
+    }
+    if let Some(message) = parsed.get_mut("size") {
+        receive_size(message.take())?;
+    }
+    if let Some(message) = parsed.get_mut("resources") {
+        receive_resources(message.take())?;
+    }
+    if let Some(message) = parsed.get_mut("fallback") {
+        receive_fallback(message.take())?;
+    }
+    if let Some(message) = parsed.get_mut("render") {
+        receive_render(message.take())?;
+    }
+    if let Some(message) = parsed.get_mut("dump") {
+        reply.insert("dump".to_string(), receive_dump(message.take())?);
+    }
+    if let Some(message) = parsed.get_mut("svg") {
+        reply.insert("svg".to_string(), receive_svg(message.take())?);
+    }
+    if let Some(message) = parsed.get_mut("screenshot") {
+        reply.insert("screenshot".to_string(), receive_screenshot(message.take())?);
+    }
+    if let Some(message) = parsed.get_mut("stats") {
+        reply.insert("stats".to_string(), receive_stats(message.take()));
+    }
+
+    if reply.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(serde_json::Value::Object(reply)))
+    }
+}
+
+pub fn receive_clear() {
+    let mut remote_resources = REMOTE_RESOURCES.lock().unwrap();
+    let mut remote_display_list = REMOTE_DISPLAY_LIST.lock().unwrap();
+    remote_resources.clear();
+    remote_display_list.clear();
+    FONT_FALLBACK.lock().unwrap().session.clear();
+    TEXT_RUNS.lock().unwrap().clear();
+    SHOULD_REDRAW.swap(true, Ordering::Relaxed);
+}
+
+fn receive_position(parsed: serde_json::Value) -> Result<()> {
+    let position = (
+        get_index(&parsed, 0, "position")?.as_i64().ok_or(ProtocolError::InvalidField("position"))? as i32,
+        get_index(&parsed, 1, "position")?.as_i64().ok_or(ProtocolError::InvalidField("position"))? as i32
+    );
+    log_event!(Level::Info, "window", "Moving window", x = position.0, y = position.1);
+    *SHOULD_SET_WINDOW_POSITION.lock().unwrap() = Some(position);
+    Ok(())
+}
+
+fn receive_size(parsed: serde_json::Value) -> Result<()> {
+    let size = (
+        as_u32(get_index(&parsed, 0, "size")?, "size")?,
+        as_u32(get_index(&parsed, 1, "size")?, "size")?
+    );
+    log_event!(Level::Info, "window", "Resizing window", width = size.0, height = size.1);
+    *WINDOW_SIZE.lock().unwrap() = Some(size);
+    *SHOULD_SET_WINDOW_SIZE.lock().unwrap() = Some(size);
+    Ok(())
+}
+
+fn receive_resources(parsed: serde_json::Value) -> Result<()> {
+    // Decoding can touch the file system, to look fonts up, which mustn't
+    // hold up the runtime taking resource updates.
+    let resources = decode_resources(parsed)?;
+    let mut remote_resources = REMOTE_RESOURCES.lock().unwrap();
+    let registered_resources = REGISTERED_RESOURCES.lock().unwrap();
+
+    for resource in resources {
+        // Font instances can only refer to fonts that were added before them,
+        // either in an earlier message or earlier in this one.
+        if let RemoteResource::FontInstance(ref font_instance) = resource {
+            if !has_font(&remote_resources, font_instance.key) && !has_font(&registered_resources, font_instance.key) {
+                return Err(ProtocolError::UnknownFont(font_instance.key));
+            }
+        }
+        match resource {
+            RemoteResource::Font(ref font) => log_event!(
+                Level::Info,
+                "resources",
+                "Font added",
+                key = font.key,
+                bytes = font.data_uri.as_ref().map(|data_uri| data_uri.len()),
+                path = font.path.as_ref().map(|path| path.display().to_string())
+            ),
+            RemoteResource::FontInstance(ref font_instance) => log_event!(
+                Level::Info,
+                "resources",
+                "Font instance added",
+                key = font_instance.key,
+                instance_key = font_instance.instance_key,
+                size = font_instance.size
+            ),
+            _ => {}
+        }
+        remote_resources.push(resource);
+    }
+
+    Ok(())
+}
+
+/// Sets the session's fallback chain, replacing the previous one. Like font
+/// instances, it can only refer to fonts that were already added.
+fn receive_fallback(parsed: serde_json::Value) -> Result<()> {
+    let remote_resources = REMOTE_RESOURCES.lock().unwrap();
+    let registered_resources = REGISTERED_RESOURCES.lock().unwrap();
+
+    let keys = parsed
+        .as_array()
+        .ok_or(ProtocolError::InvalidField("fallback"))?
+        .iter()
+        .map(|key| as_u64(key, "fallback"))
+        .collect::<Result<Vec<_>>>()?;
+    if let Some(&key) = keys.iter().find(|&&key| !has_font(&remote_resources, key) && !has_font(&registered_resources, key)) {
+        return Err(ProtocolError::UnknownFont(key));
+    }
+    log_event!(Level::Info, "resources", "Fallback fonts set", keys = keys);
+    FONT_FALLBACK.lock().unwrap().session = keys;
+    TEXT_RUNS.lock().unwrap().clear();
+    SHOULD_REDRAW.swap(true, Ordering::Relaxed);
+    Ok(())
+}
+
+fn receive_render(parsed: serde_json::Value) -> Result<()> {
+    let mut remote_display_list = REMOTE_DISPLAY_LIST.lock().unwrap();
+    let diffs = parsed.as_array().map_or(0, |diffs| diffs.len());
+    let result = metrics::time(Stage::Diff, || apply_render(&mut remote_display_list, parsed));
+    log_event!(Level::Trace, "render", "Applied diffs", diffs = diffs, items = remote_display_list.len());
+    SHOULD_REDRAW.swap(true, Ordering::Relaxed);
+    result
+}
+
+/// Decodes a list of resource updates, as sent under the `resources` key.
+pub fn decode_resources(mut parsed: serde_json::Value) -> Result<Vec<RemoteResource>> {
+    let mut remote_resources = vec![];
+
+    for diff in parsed.as_array_mut().ok_or(ProtocolError::InvalidField("resources"))? {
+        if let Some(update) = diff.get_mut("AddFont") {
+            let key = as_u64(get(update, "key")?, "key")?;
+            if font_fallback::is_global_key(key) {
+                return Err(ProtocolError::ReservedFontKey(key));
+            }
+            // Fonts come inline, or from a local file, or from the installed
+            // fonts. Either of the latter two has to resolve to a file that's
+            // there now, rather than fail when the font gets registered. The
+            // face of an installed family is picked by the descriptors the
+            // font is added with.
+            let (weight, style, stretch) = get_font_properties(update)?;
+            let mut font = RemoteFontResource {
+                key,
+                data_uri: None,
+                path: None,
+                weight,
+                style,
+                stretch,
+                file_size: None
+            };
+            if update.get("data_uri").is_some() {
+                font.data_uri = Some(take_string(get_mut(update, "data_uri")?.take()).ok_or(ProtocolError::InvalidField("data_uri"))?);
+            } else if let Some(path) = update.get("path") {
+                let path = path.as_str().ok_or(ProtocolError::InvalidField("path"))?;
+                font.set_path(font_path(path)?);
+            } else if let Some(family) = update.get("family") {
+                let family = family.as_str().ok_or(ProtocolError::InvalidField("family"))?;
+                let path = SYSTEM_FONTS.lock().unwrap().find(family, &font.descriptor(None));
+                font.set_path(path.ok_or_else(|| ProtocolError::UnknownFontFamily(family.to_string()))?);
+            } else {
+                return Err(ProtocolError::MissingField("data_uri"));
+            }
+            remote_resources.push(RemoteResource::Font(font));
+            continue;
+        }
+        if let Some(update) = diff.get("AddFontInstance") {
+            let key = as_u64(get(update, "key")?, "key")?;
+            let instance_key = as_u64(get(update, "instance_key")?, "instance_key")?;
+            let size = as_u32(get(update, "size")?, "size")?;
+            remote_resources.push(RemoteResource::FontInstance(RemoteFontInstanceResource {
+                key,
+                instance_key,
+                size
+            }));
+            continue;
+        }
+        return Err(ProtocolError::UnknownUpdate(diff.to_string()));
+    }
+
+    Ok(remote_resources)
+}
+
+/// Applies a display list diff, as sent under the `render` key, to
+/// `remote_display_list`. Diffs before a failing one stay applied.
+pub fn apply_render(remote_display_list: &mut Vec<RemoteDisplayItem>, mut parsed: serde_json::Value) -> Result<()> {
+    for diff in parsed.as_array_mut().ok_or(ProtocolError::InvalidField("render"))? {
+        if let Some(update) = diff.get_mut(Diff::UPDATE_SELF_KEY) {
+            let i = update
+                .get(0)
+                .and_then(|v| v.as_u64())
+                .ok_or(ProtocolError::InvalidField(Diff::UPDATE_SELF_KEY))? as usize;
+
+            if remote_display_list.len() == 0 {
+                // Server started after the page was loaded in host.
+                return Ok(());
+            }
+
+            if i >= remote_display_list.len() {
+                return Err(ProtocolError::IndexOutOfRange(i));
+            }
+            let changes = update
+                .get_mut(1)
+                .and_then(|v| v.as_array_mut())
+                .ok_or(ProtocolError::InvalidField(Diff::UPDATE_SELF_KEY))?;
+
+            for change in changes {
+                if let Some(specific) = change.get_mut(Diff::TEXT_UPDATE_KEY) {
+                    if let Some(text_content) = specific.get_mut(Diff::TEXT_UPDATE_CHANGE_CONTENT_KEY) {
+                        let text = take_string(text_content.take())
+                            .ok_or(ProtocolError::InvalidField(Diff::TEXT_UPDATE_CHANGE_CONTENT_KEY))?;
+                        invalidate_runs(remote_display_list, i);
+                        remote_display_list[i]
+                            .as_text()
+                            .ok_or(ProtocolError::UnexpectedItem(i))?
+                            .set_text(text);
+                        continue;
+                    }
+                }
+                if let Some(specific) = change.get(Diff::BOUNDS_UPDATE_KEY) {
+                    let rect = remote_display_list[i].rect_mut().ok_or(ProtocolError::UnexpectedItem(i))?;
+                    if let Some(left) = specific.get(Diff::BOUNDS_UPDATE_CHANGE_X_KEY) {
+                        rect.position.left = as_u32(left, Diff::BOUNDS_UPDATE_CHANGE_X_KEY)?;
+                        continue;
+                    }
+                    if let Some(top) = specific.get(Diff::BOUNDS_UPDATE_CHANGE_Y_KEY) {
+                        rect.position.top = as_u32(top, Diff::BOUNDS_UPDATE_CHANGE_Y_KEY)?;
+                        continue;
+                    }
+                    if let Some(width) = specific.get(Diff::BOUNDS_UPDATE_CHANGE_WIDTH_KEY) {
+                        rect.size.width = as_u32(width, Diff::BOUNDS_UPDATE_CHANGE_WIDTH_KEY)?;
+                        continue;
+                    }
+                    if let Some(height) = specific.get(Diff::BOUNDS_UPDATE_CHANGE_HEIGHT_KEY) {
+                        rect.size.height = as_u32(height, Diff::BOUNDS_UPDATE_CHANGE_HEIGHT_KEY)?;
+                        continue;
+                    }
+                }
+            }
+            continue;
+        }
+        if let Some(update) = diff.get_mut(Diff::ADD_RECT_KEY) {
+            remote_display_list.push(RemoteDisplayItem::Rect(RemoteRectItem {
+                rect: get_bounding_client_rect(update)?,
+                color: get_color(update)?
+            }));
+            continue;
+        }
+        if let Some(update) = diff.get_mut(Diff::ADD_BORDER_KEY) {
+            remote_display_list.push(RemoteDisplayItem::Border(RemoteBorderItem {
+                rect: get_bounding_client_rect(update)?,
+                colors: get_border_colors(update)?,
+                styles: get_border_styles(update),
+                widths: get_border_widths(update)
+            }));
+            continue;
+        }
+        if let Some(update) = diff.get_mut(Diff::ADD_TEXT_KEY) {
+            let color = get_color(update)?;
+            let (text, runs) = get_text_runs(update, color)?;
+            remote_display_list.push(RemoteDisplayItem::Text(RemoteTextItem {
+                rect: get_bounding_client_rect(update)?,
+                color,
+                text,
+                runs,
+                layout: get_text_layout(update)?
+            }));
+            continue;
+        }
+        return Err(ProtocolError::UnknownUpdate(diff.to_string()));
+    }
+
+    Ok(())
+}
+
+/// The file a font is added from by `path`, which has to be in one of the
+/// directories the renderer was started with `--font-dir`. Clients don't get
+/// to read anything else, so the path has to be absolute, and it's checked
+/// again once links are resolved.
+fn font_path(path: &str) -> Result<PathBuf> {
+    let font_dirs = FONT_DIRS.lock().unwrap().clone();
+    let allowed = |path: &Path| font_dirs.iter().any(|dir| path.starts_with(dir));
+    let requested = Path::new(path);
+    if !requested.is_absolute() || requested.components().any(|component| component == Component::ParentDir) || !allowed(requested) {
+        return Err(ProtocolError::ForbiddenFontPath(path.to_string()));
+    }
+    let resolved = fs::canonicalize(requested)?;
+    if !allowed(&resolved) {
+        return Err(ProtocolError::ForbiddenFontPath(path.to_string()));
+    }
+    Ok(resolved)
+}
+
+/// Where a `{ path }` request has its file written: `path` within the
+/// directory the renderer was started with `--dump-dir`. Clients don't get to
+/// write anywhere else, so without one, or for absolute paths and paths
+/// leading out of it, the request is rejected.
+fn dump_path(path: &str) -> Result<PathBuf> {
+    let dump_dir = DUMP_DIR.lock().unwrap().clone();
+    let escapes = Path::new(path).components().any(|component| match component {
+        Component::Normal(_) | Component::CurDir => false,
+        Component::Prefix(_) | Component::RootDir | Component::ParentDir => true
+    });
+    match dump_dir {
+        Some(ref dump_dir) if !escapes => Ok(dump_dir.join(path)),
+        _ => Err(ProtocolError::ForbiddenPath(path.to_string()))
+    }
+}
+
+fn receive_dump(parsed: serde_json::Value) -> Result<serde_json::Value> {
+    // A `{ path }` object writes the dump to disk, anything else returns it inline.
+    if let Some(path) = parsed.get("path").and_then(|v| v.as_str()) {
+        Snapshot::capture().write(dump_path(path)?)?;
+        return Ok(json!({ "path": path }));
+    }
+    Ok(serde_json::to_value(Snapshot::capture())?)
+}
+
+fn receive_svg(parsed: serde_json::Value) -> Result<serde_json::Value> {
+    // Same as dumps: a `{ path }` object writes the document to disk.
+    let document = svg::export(&Snapshot::capture());
+    if let Some(path) = parsed.get("path").and_then(|v| v.as_str()) {
+        File::create(dump_path(path)?)?.write_all(document.as_bytes())?;
+        return Ok(json!({ "path": path }));
+    }
+    Ok(serde_json::Value::String(document))
+}
+
+fn receive_screenshot(parsed: serde_json::Value) -> Result<serde_json::Value> {
+    // Screenshots are rasterized in software, so they work with any GL setup
+    // but are only an approximation, with text drawn as a block per
+    // character. Inline replies carry the PNG as base64.
+    let png = Snapshot::capture().rasterize().encode_png()?;
+    if let Some(path) = parsed.get("path").and_then(|v| v.as_str()) {
+        File::create(dump_path(path)?)?.write_all(&png)?;
+        return Ok(json!({ "path": path }));
+    }
+    Ok(json!({ "png": base64::encode(&png) }))
+}
+
+fn receive_stats(parsed: serde_json::Value) -> serde_json::Value {
+    // A `{ reset: true }` object starts over once the current stats are taken.
+    // The metrics and the shaping cache are never held at once, here or by
+    // frames being built, so that neither side waits on the other.
+    let reset = parsed.get("reset").and_then(|v| v.as_bool()) == Some(true);
+    let mut summary = {
+        let mut frame_metrics = FRAME_METRICS.lock().unwrap();
+        let summary = frame_metrics.summary();
+        if reset {
+            frame_metrics.reset();
+        }
+        summary
+    };
+    summary["shaping"] = {
+        let mut shaping_cache = SHAPING_CACHE.lock().unwrap();
+        let summary = shaping_cache.summary();
+        if reset {
+            shaping_cache.reset_stats();
+        }
+        summary
+    };
+    summary
+}
+
+/// Drops the cached pieces of the text item at `index` and their shaping
+/// once its text changes, rather than leaving text it no longer shows to be
+/// evicted eventually. Items showing the same text in the same font share
+/// its shaping, so it's kept for as long as another item still shows it.
+fn invalidate_runs(remote_display_list: &[RemoteDisplayItem], index: usize) {
+    let item = match remote_display_list.get(index) {
+        Some(&RemoteDisplayItem::Text(ref item)) => item,
+        _ => return
+    };
+    let mut text_runs = TEXT_RUNS.lock().unwrap();
+    let resolved = text_runs.invalidate(index);
+    // The pieces are gone if a font or the fallback chain changed since the
+    // item was last drawn, and then its own runs are the best guess at what
+    // was shaped.
+    let runs = match resolved {
+        Some(ref resolved) => resolved.iter().map(|resolved| resolved.run(item)).collect::<Vec<_>>(),
+        None => item.runs.iter().collect()
+    };
+    let mut shaping_cache = SHAPING_CACHE.lock().unwrap();
+    for run in runs {
+        let text = item.run_text(run);
+        let shows = |other: &RemoteTextItem, other_run: &RemoteTextRun| {
+            other_run.font_key == run.font_key && other_run.font_instance_key == run.font_instance_key && other.run_text(other_run) == text
+        };
+        let shared = remote_display_list.iter().enumerate().any(|(other_index, other)| match other {
+            &RemoteDisplayItem::Text(ref other) if other_index != index => match text_runs.get(other_index) {
+                Some(resolved) => resolved.iter().any(|resolved| shows(other, resolved.run(other))),
+                None => other.runs.iter().any(|other_run| shows(other, other_run))
+            },
+            _ => false
+        });
+        if !shared {
+            shaping_cache.invalidate(run.font_key, run.font_instance_key, text);
+        }
+    }
+}
+
+fn has_font(resources: &[RemoteResource], key: u64) -> bool {
+    resources.iter().any(|resource| match resource {
+        &RemoteResource::Font(ref font) => font.key == key,
+        _ => false
+    })
+}
+
+fn take_string(value: serde_json::Value) -> Option<String> {
+    if let serde_json::Value::String(string) = value {
+        Some(string)
+    } else {
+        None
+    }
+}
+
+fn get<'a>(value: &'a serde_json::Value, name: &'static str) -> Result<&'a serde_json::Value> {
+    value.get(name).ok_or(ProtocolError::MissingField(name))
+}
+
+fn get_mut<'a>(value: &'a mut serde_json::Value, name: &'static str) -> Result<&'a mut serde_json::Value> {
+    value.get_mut(name).ok_or(ProtocolError::MissingField(name))
+}
+
+fn get_index<'a>(value: &'a serde_json::Value, index: usize, name: &'static str) -> Result<&'a serde_json::Value> {
+    value.get(index).ok_or(ProtocolError::MissingField(name))
+}
+
+fn as_u64(value: &serde_json::Value, name: &'static str) -> Result<u64> {
+    value.as_u64().ok_or(ProtocolError::InvalidField(name))
+}
+
+fn as_u32(value: &serde_json::Value, name: &'static str) -> Result<u32> {
+    as_u64(value, name).map(|v| v as u32)
+}
+
+fn as_u8(value: &serde_json::Value, name: &'static str) -> Result<u8> {
+    as_u64(value, name).map(|v| v as u8)
+}
+
+fn as_f32(value: &serde_json::Value, name: &'static str) -> Result<f32> {
+    value.as_f64().map(|v| v as f32).ok_or(ProtocolError::InvalidField(name))
+}
+
+fn get_bounding_client_rect(value: &serde_json::Value) -> Result<LayoutBoundingClientRect> {
+    let bounds = get(value, "bounds")?;
+    let position = get(bounds, "position")?;
+    let size = get(bounds, "size")?;
+    let left = as_u32(get(position, "left")?, "left")?;
+    let top = as_u32(get(position, "top")?, "top")?;
+    let width = as_u32(get(size, "width")?, "width")?;
+    let height = as_u32(get(size, "height")?, "height")?;
+    Ok(LayoutBoundingClientRect::new(left, top, width, height))
+}
+
+fn parse_color(color: &serde_json::Value) -> Result<Color> {
+    let red = as_u8(get(color, "red")?, "red")?;
+    let green = as_u8(get(color, "green")?, "green")?;
+    let blue = as_u8(get(color, "blue")?, "blue")?;
+    let alpha = as_u8(get(color, "alpha")?, "alpha")?;
+    Ok(Color::new([red, green, blue, alpha]))
+}
+
+fn get_color(value: &serde_json::Value) -> Result<Color> {
+    let display = get(value, "display")?;
+    parse_color(get(display, "color")?)
+}
+
+fn get_border_color(value: &serde_json::Value, index: usize) -> Result<Color> {
+    let display = get(value, "display")?;
+    parse_color(get_index(get(display, "colors")?, index, "colors")?)
+}
+
+fn get_border_style(_value: &serde_json::Value, _index: usize) -> BorderStyle {
+    // TODO
+    BorderStyle::Solid
+}
+
+fn get_border_width(_value: &serde_json::Value, _index: usize) -> u32 {
+    // TODO
+    1
+}
+
+fn get_border_colors(value: &serde_json::Value) -> Result<[Color; 4]> {
+    Ok([
+        get_border_color(value, 0)?,
+        get_border_color(value, 1)?,
+        get_border_color(value, 2)?,
+        get_border_color(value, 3)?,
+    ])
+}
+
+fn get_border_styles(value: &serde_json::Value) -> [BorderStyle; 4] {
+    [
+        get_border_style(value, 0),
+        get_border_style(value, 1),
+        get_border_style(value, 2),
+        get_border_style(value, 3),
+    ]
+}
+
+fn get_border_widths(value: &serde_json::Value) -> [u32; 4] {
+    [
+        get_border_width(value, 0),
+        get_border_width(value, 1),
+        get_border_width(value, 2),
+        get_border_width(value, 3),
+    ]
+}
+
+/// Text comes as a list of source pieces, along with shaping information
+/// for each. When both lists line up every piece becomes a run with its own
+/// font and, optionally, color; otherwise the first font is used for all of
+/// the text.
+fn get_text_runs(value: &serde_json::Value, color: Color) -> Result<(String, Vec<RemoteTextRun>)> {
+    let display = get(value, "display")?;
+    let pieces = get(display, "source_text")?
+        .as_array()
+        .ok_or(ProtocolError::InvalidField("source_text"))?
+        .iter()
+        .filter_map(|v| v.get("Owned").or(v.get("Static")))
+        .filter_map(|v| v.as_str())
+        .collect::<Vec<&str>>();
+    let shaped_text = get(display, "shaped_text")?
+        .as_array()
+        .ok_or(ProtocolError::InvalidField("shaped_text"))?;
+
+    let text = pieces.concat();
+    if shaped_text.len() > 1 && shaped_text.len() == pieces.len() {
+        let mut runs = vec![];
+        let mut start = 0;
+        for (piece, shaped) in pieces.iter().zip(shaped_text) {
+            runs.push(get_text_run(shaped, start..start + piece.len(), color)?);
+            start += piece.len();
+        }
+        return Ok((text, runs));
+    }
+
+    let shaped = shaped_text.get(0).ok_or(ProtocolError::MissingField("shaped_text"))?;
+    let run = get_text_run(shaped, 0..text.len(), color)?;
+    Ok((text, vec![run]))
+}
+
+fn get_text_run(shaped: &serde_json::Value, range: Range<usize>, color: Color) -> Result<RemoteTextRun> {
+    Ok(RemoteTextRun {
+        font_key: as_u64(get(shaped, "font_key")?, "font_key")?,
+        font_instance_key: as_u64(get(shaped, "font_instance_key")?, "font_instance_key")?,
+        start: range.start,
+        end: range.end,
+        color: match shaped.get("color") {
+            Some(color) => parse_color(color)?,
+            None => color
+        },
+        glyphs: match shaped.get("glyphs") {
+            Some(glyphs) => Some(get_glyphs(glyphs)?),
+            None => None
+        },
+        decorations: match shaped.get("decorations") {
+            Some(decorations) => get_decorations(decorations)?,
+            None => vec![]
+        },
+        face: match get_font_properties(shaped)? {
+            (None, None, None) => None,
+            (weight, style, stretch) => {
+                let normal = FontDescriptor::default();
+                Some(FontDescriptor {
+                    weight: weight.unwrap_or(normal.weight),
+                    style: style.unwrap_or(normal.style),
+                    stretch: stretch.unwrap_or(normal.stretch)
+                })
+            }
+        }
+    })
+}
+
+/// Reads the optional `weight`, `style` and `stretch` fonts are added with,
+/// and text runs ask for.
+fn get_font_properties(value: &serde_json::Value) -> Result<(Option<u16>, Option<FontStyle>, Option<f32>)> {
+    let weight = match value.get("weight") {
+        Some(weight) => match as_u64(weight, "weight")? {
+            weight if weight >= 1 && weight <= 1000 => Some(weight as u16),
+            _ => return Err(ProtocolError::InvalidField("weight"))
+        },
+        None => None
+    };
+    let style = match value.get("style").map(|style| style.as_str()) {
+        Some(Some("normal")) => Some(FontStyle::Normal),
+        Some(Some("italic")) => Some(FontStyle::Italic),
+        Some(Some("oblique")) => Some(FontStyle::Oblique),
+        Some(_) => return Err(ProtocolError::InvalidField("style")),
+        None => None
+    };
+    let stretch = match value.get("stretch") {
+        Some(stretch) => match as_f32(stretch, "stretch")? {
+            stretch if stretch > 0.0 => Some(stretch),
+            _ => return Err(ProtocolError::InvalidField("stretch"))
+        },
+        None => None
+    };
+    Ok((weight, style, stretch))
+}
+
+fn get_decorations(decorations: &serde_json::Value) -> Result<Vec<RemoteTextDecoration>> {
+    decorations
+        .as_array()
+        .ok_or(ProtocolError::InvalidField("decorations"))?
+        .iter()
+        .map(|decoration| {
+            Ok(RemoteTextDecoration {
+                line: match get(decoration, "line")?.as_str() {
+                    Some("underline") => DecorationLine::Underline,
+                    Some("overline") => DecorationLine::Overline,
+                    Some("line-through") => DecorationLine::LineThrough,
+                    _ => return Err(ProtocolError::InvalidField("line"))
+                },
+                color: match decoration.get("color") {
+                    Some(color) => Some(parse_color(color)?),
+                    None => None
+                },
+                thickness: match decoration.get("thickness") {
+                    Some(thickness) => Some(as_f32(thickness, "thickness")?),
+                    None => None
+                },
+                style: match decoration.get("style").map(|style| style.as_str()) {
+                    Some(Some("solid")) | None => DecorationStyle::Solid,
+                    Some(Some("double")) => DecorationStyle::Double,
+                    Some(Some("dotted")) => DecorationStyle::Dotted,
+                    Some(Some("dashed")) => DecorationStyle::Dashed,
+                    Some(Some("wavy")) => DecorationStyle::Wavy,
+                    Some(_) => return Err(ProtocolError::InvalidField("style"))
+                }
+            })
+        })
+        .collect()
+}
+
+fn get_text_layout(value: &serde_json::Value) -> Result<RemoteTextLayout> {
+    let layout = match get(value, "display")?.get("layout") {
+        Some(layout) => layout,
+        None => return Ok(Default::default())
+    };
+    Ok(RemoteTextLayout {
+        wrap: match layout.get("wrap") {
+            Some(wrap) => wrap.as_bool().ok_or(ProtocolError::InvalidField("wrap"))?,
+            None => false
+        },
+        line_height: match layout.get("line_height") {
+            Some(line_height) => Some(as_f32(line_height, "line_height")?),
+            None => None
+        },
+        align: match layout.get("align").map(|align| align.as_str()) {
+            Some(Some("left")) | None => TextAlign::Left,
+            Some(Some("center")) => TextAlign::Center,
+            Some(Some("right")) => TextAlign::Right,
+            Some(_) => return Err(ProtocolError::InvalidField("align"))
+        },
+        direction: match layout.get("direction").map(|direction| direction.as_str()) {
+            Some(Some("ltr")) | None => TextDirection::Ltr,
+            Some(Some("rtl")) => TextDirection::Rtl,
+            Some(Some("auto")) => TextDirection::Auto,
+            Some(_) => return Err(ProtocolError::InvalidField("direction"))
+        },
+        orientation: match layout.get("orientation").map(|orientation| orientation.as_str()) {
+            Some(Some("horizontal")) | None => TextOrientation::Horizontal,
+            Some(Some("vertical")) => TextOrientation::Vertical,
+            Some(_) => return Err(ProtocolError::InvalidField("orientation"))
+        },
+        overflow: match layout.get("overflow").map(|overflow| overflow.as_str()) {
+            Some(Some("visible")) | None => TextOverflow::Visible,
+            Some(Some("clip")) => TextOverflow::Clip,
+            Some(Some("ellipsis")) => TextOverflow::Ellipsis,
+            Some(Some("ellipsis-middle")) => TextOverflow::EllipsisMiddle,
+            Some(_) => return Err(ProtocolError::InvalidField("overflow"))
+        }
+    })
+}
+
+fn get_glyphs(glyphs: &serde_json::Value) -> Result<Vec<RemoteGlyph>> {
+    glyphs
+        .as_array()
+        .ok_or(ProtocolError::InvalidField("glyphs"))?
+        .iter()
+        .map(|glyph| {
+            Ok(RemoteGlyph {
+                index: as_u32(get(glyph, "index")?, "index")?,
+                advance: as_f32(get(glyph, "advance")?, "advance")?
+            })
+        })
+        .collect()
+}
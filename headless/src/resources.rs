@@ -0,0 +1,118 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+use base64;
+
+use font_matching::{FontDescriptor, FontStyle};
+use sfnt::Face;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum RemoteResource {
+    Font(RemoteFontResource),
+    FontInstance(RemoteFontInstanceResource),
+    Image(RemoteImageResource)
+}
+
+/// A font sent inline as a base64 data URI, or read from a local file, which
+/// is also what fonts added by system family name are resolved to. Exactly
+/// one of the two is set. The weight, style and stretch the font is
+/// registered with override what the font itself says.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RemoteFontResource {
+    pub key: u64,
+    pub data_uri: Option<String>,
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    #[serde(default)]
+    pub weight: Option<u16>,
+    #[serde(default)]
+    pub style: Option<FontStyle>,
+    #[serde(default)]
+    pub stretch: Option<f32>,
+    /// The size of the file at `path` when the font was added.
+    #[serde(skip)]
+    pub file_size: Option<u64>
+}
+
+impl RemoteFontResource {
+    /// Points the font at a local file, noting how big it is.
+    pub fn set_path(&mut self, path: PathBuf) {
+        self.file_size = fs::metadata(&path).map(|metadata| metadata.len()).ok();
+        self.path = Some(path);
+    }
+
+    /// How many bytes the font takes: its decoded data if it was sent inline,
+    /// or its file as it was when added.
+    pub fn size(&self) -> usize {
+        match (&self.data_uri, self.file_size) {
+            (&Some(ref data_uri), _) => data_uri.splitn(2, ',').nth(1).unwrap_or("").len() * 3 / 4,
+            (&None, Some(file_size)) => file_size as usize,
+            (&None, None) => 0
+        }
+    }
+
+    /// Parses the font, for laying text out on this side.
+    pub fn face(&self) -> Option<Face> {
+        match (&self.data_uri, &self.path) {
+            (&Some(ref data_uri), _) => Face::from_data_uri(data_uri),
+            (&None, &Some(ref path)) => Face::from_path(path),
+            (&None, &None) => None
+        }
+    }
+
+    /// Reads a font added from a local file into a data URI, so that it
+    /// doesn't depend on the file anymore. Fonts sent inline are left as is.
+    pub fn embed(&mut self) -> io::Result<()> {
+        let path = match (&self.data_uri, &self.path) {
+            (&None, &Some(ref path)) => path.clone(),
+            _ => return Ok(())
+        };
+        let mut data = vec![];
+        File::open(&path)?.read_to_end(&mut data)?;
+        let mime_type = match path.extension().and_then(|extension| extension.to_str()).map(|extension| extension.to_lowercase()) {
+            Some(ref extension) if extension == "otf" => "font/otf",
+            Some(ref extension) if extension == "ttc" => "font/collection",
+            _ => "font/ttf"
+        };
+        self.data_uri = Some(format!("data:{};base64,{}", mime_type, base64::encode(&data)));
+        self.path = None;
+        self.file_size = None;
+        Ok(())
+    }
+
+    /// The face's weight, style and stretch, as registered or as read from
+    /// `face`, which is this font parsed.
+    pub fn descriptor(&self, face: Option<&Face>) -> FontDescriptor {
+        let own = face.map_or_else(FontDescriptor::default, FontDescriptor::from_face);
+        FontDescriptor {
+            weight: self.weight.unwrap_or(own.weight),
+            style: self.style.unwrap_or(own.style),
+            stretch: self.stretch.unwrap_or(own.stretch)
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RemoteFontInstanceResource {
+    pub key: u64,
+    pub instance_key: u64,
+    pub size: u32
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RemoteImageResource {
+    // TODO
+}
+
@@ -0,0 +1,348 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use rsx_native_renderer::glutin::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use rsx_native_renderer::webrender::api::{BuiltDisplayList, LayoutSize, PipelineId, RenderApi, ResourceUpdates};
+use rsx_primitives::prelude::{DOMTree, FileCache, FontCache, ImageCache, ResourceGroup};
+use rsx_primitives::rsx_resources::fonts::types::EncodedFont;
+use rsx_primitives::rsx_shared::traits::{
+    TEncodedFont,
+    TFileCache,
+    TFontCache,
+    TFontKeysAPI,
+    TImageCache,
+    TImageKeysAPI,
+    TResourceGroup,
+    TRuntime
+};
+use rsx_primitives::rsx_stylesheet::types::{BorderStyle, Color};
+
+use backend::{self, Fonts, TRenderBackend, TextFont, WebRenderBackend};
+use logging::Level;
+use metrics::Stage;
+use overlay::{self, Overlay};
+use resources::{RemoteFontResource, RemoteResource};
+use sfnt::Face;
+use snapshot::Snapshot;
+use state::{
+    DUMP_DIR,
+    FONT_DESCRIPTORS,
+    FONT_FACES,
+    FONT_FALLBACK,
+    FONT_FAMILY_NAME_MAP,
+    FONT_SIZE_MAP,
+    FRAME_METRICS,
+    REGISTERED_RESOURCES,
+    REMOTE_DISPLAY_LIST,
+    REMOTE_RESOURCES,
+    SHAPING_CACHE,
+    SHOULD_REDRAW,
+    SHOULD_SET_WINDOW_POSITION,
+    SHOULD_SET_WINDOW_SIZE,
+    SHOW_OVERLAY,
+    TEXT_RUNS
+};
+
+pub struct Runtime {
+    resources: ResourceGroup,
+    overlay: Overlay,
+    overlay_font: Option<String>,
+    fallback: Vec<u64>,
+    pub highlighted_item: Option<usize>
+}
+
+impl TRuntime for Runtime {
+    type RootRendererAPI = Rc<RenderApi>;
+    type DOMResources = ResourceGroup;
+    type DOMTree = DOMTree;
+    type VirtualEventMetadata = (Event,);
+    type ReflowMetadata = (PipelineId, LayoutSize);
+    type BuiltDisplayList = BuiltDisplayList;
+    type ResourceUpdates = ResourceUpdates;
+
+    fn new<S, R>(api: &Self::RootRendererAPI, _: S, _: R) -> Self
+    where
+        S: Fn(&mut Self::DOMResources),
+        R: Fn() -> Self::DOMTree
+    {
+        let files = FileCache::new().unwrap();
+        let images = ImageCache::new(TImageKeysAPI::new(Rc::clone(api))).unwrap();
+        let fonts = FontCache::new(TFontKeysAPI::new(Rc::clone(api))).unwrap();
+        let resources = ResourceGroup::new(files, images, fonts);
+
+        let mut runtime = Runtime {
+            resources,
+            overlay: Overlay::new(),
+            overlay_font: None,
+            fallback: vec![],
+            highlighted_item: None
+        };
+
+        // Global fallback fonts aren't any client's, so they're registered
+        // up front and stay registered across sessions.
+        let global_fonts = FONT_FALLBACK.lock().unwrap().global_fonts.clone();
+        for font in global_fonts {
+            if runtime.register_font(&font) {
+                REGISTERED_RESOURCES.lock().unwrap().push(RemoteResource::Font(font));
+            }
+        }
+        runtime
+    }
+
+    fn should_set_window_position(&mut self) -> Option<(i32, i32)> {
+        SHOULD_SET_WINDOW_POSITION.lock().unwrap().take()
+    }
+
+    fn should_set_window_size(&mut self) -> Option<(u32, u32)> {
+        SHOULD_SET_WINDOW_SIZE.lock().unwrap().take()
+    }
+
+    fn should_redraw(&mut self) -> bool {
+        FRAME_METRICS.lock().unwrap().runner_polled();
+        SHOULD_REDRAW.swap(false, Ordering::Relaxed)
+    }
+
+    fn handle_event(&mut self, (event,): Self::VirtualEventMetadata) -> bool {
+        if let Event::WindowEvent {
+            event: WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(key),
+                    ..
+                },
+                ..
+            },
+            ..
+        } = event
+        {
+            match key {
+                VirtualKeyCode::F11 => {
+                    SHOW_OVERLAY.fetch_xor(true, Ordering::Relaxed);
+                    SHOULD_REDRAW.swap(true, Ordering::Relaxed);
+                }
+                VirtualKeyCode::F12 => {
+                    // Dumps go where `dump` requests write theirs, so there's
+                    // nowhere for them to go without a `--dump-dir`.
+                    let dump_dir = DUMP_DIR.lock().unwrap().clone();
+                    match dump_dir {
+                        Some(dump_dir) => {
+                            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+                            let path = dump_dir.join(format!("display-list-{}.json", timestamp));
+                            let display = path.display().to_string();
+                            match Snapshot::capture().write(&path) {
+                                Ok(()) => log_event!(Level::Info, "snapshot", "Dumped display list", path = display),
+                                Err(err) => log_event!(Level::Error, "snapshot", "Couldn't dump display list", path = display, error = err.to_string())
+                            }
+                        }
+                        None => log_event!(Level::Warn, "snapshot", "Not dumping the display list without a --dump-dir")
+                    }
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
+    fn take_resource_updates(&mut self) -> Self::ResourceUpdates {
+        let start = Instant::now();
+        let mut remote_resources = REMOTE_RESOURCES.lock().unwrap();
+        let mut registered_resources = REGISTERED_RESOURCES.lock().unwrap();
+
+        let mut fonts_changed = false;
+        for resource in remote_resources.drain(..) {
+            match resource {
+                RemoteResource::Font(ref font) => {
+                    if !self.register_font(font) {
+                        continue;
+                    }
+                }
+                RemoteResource::FontInstance(ref font_instance) => {
+                    let fonts = self.resources.fonts();
+                    let font_name = format!("{}", font_instance.key);
+                    let family_name = fonts.get_family_name(font_name).unwrap();
+                    let size = font_instance.size;
+                    fonts.get_font_with_size(family_name, size);
+
+                    let mut font_size_map = FONT_SIZE_MAP.lock().unwrap();
+                    font_size_map.insert(font_instance.instance_key, size);
+                    SHAPING_CACHE.lock().unwrap().invalidate_font_instance(font_instance.instance_key);
+                    log_event!(Level::Debug, "resources", "Font instance registered", instance_key = font_instance.instance_key);
+                }
+                _ => unimplemented!()
+            }
+            // Keep registered resources around so that snapshots can embed them.
+            registered_resources.push(resource);
+            fonts_changed = true;
+        }
+
+        let fallback = FONT_FALLBACK.lock().unwrap().chain();
+        if fonts_changed || fallback != self.fallback {
+            self.fallback = fallback;
+            self.create_fallback_instances();
+        }
+
+        if SHOW_OVERLAY.load(Ordering::Relaxed) && self.overlay_font.is_none() {
+            let mut fonts = self.resources.fonts();
+            let encoded = EncodedFont::from_data_uri(overlay::font_data_uri()).unwrap();
+            fonts.add_font(overlay::FONT_NAME, &encoded, 0);
+            let family_name = fonts.get_family_name(overlay::FONT_NAME.to_string()).unwrap();
+            fonts.get_font_with_size(family_name.clone(), overlay::FONT_SIZE);
+            self.overlay_font = Some(family_name);
+        }
+
+        let images = self.resources.images().take_resource_updates();
+        let fonts = self.resources.fonts().take_resource_updates();
+        let mut updates = ResourceUpdates::new();
+        updates.merge(images);
+        updates.merge(fonts);
+
+        FRAME_METRICS.lock().unwrap().record(Stage::ResourceUpload, start.elapsed());
+        updates
+    }
+
+    fn generate_display_list(&mut self, (pipeline_id, layout_size): Self::ReflowMetadata) -> Self::BuiltDisplayList {
+        let start = Instant::now();
+        self.overlay.frame();
+
+        let remote_display_list = REMOTE_DISPLAY_LIST.lock().unwrap();
+        let font_family_name_map = FONT_FAMILY_NAME_MAP.lock().unwrap();
+        let font_size_map = FONT_SIZE_MAP.lock().unwrap();
+        let font_faces = FONT_FACES.lock().unwrap();
+        let font_descriptors = FONT_DESCRIPTORS.lock().unwrap();
+        let fallback = FONT_FALLBACK.lock().unwrap().chain();
+        let fonts = Fonts {
+            family_names: &font_family_name_map,
+            sizes: &font_size_map,
+            faces: &font_faces,
+            descriptors: &font_descriptors,
+            fallback: &fallback
+        };
+
+        let mut text_runs = TEXT_RUNS.lock().unwrap();
+        let mut shaping_cache = SHAPING_CACHE.lock().unwrap();
+
+        let mut backend = WebRenderBackend::new(pipeline_id, layout_size, &mut self.resources);
+        backend::build(&mut backend, &remote_display_list, fonts, &mut text_runs, &mut shaping_cache);
+        drop(text_runs);
+
+        if let Some(rect) = self.highlighted_item
+            .and_then(|i| remote_display_list.get(i))
+            .and_then(|item| item.rect())
+        {
+            backend.push_rect(rect, Color::new([255, 0, 255, 64]));
+            backend.push_border(rect, [1; 4], [Color::new([255, 0, 255, 255]); 4], [BorderStyle::Solid; 4]);
+        }
+
+        if SHOW_OVERLAY.load(Ordering::Relaxed) {
+            let registered_resources = REGISTERED_RESOURCES.lock().unwrap();
+            let font = self.overlay_font.as_ref().map(|family_name| TextFont {
+                family_name,
+                size: overlay::FONT_SIZE
+            });
+            self.overlay.build(&mut backend, &remote_display_list, &registered_resources, font);
+        }
+
+        let built = backend.serialize();
+        // Stats requests take the metrics and the shaping cache one at a
+        // time, which only holds as long as frames do the same.
+        drop(shaping_cache);
+
+        let mut frame_metrics = FRAME_METRICS.lock().unwrap();
+        frame_metrics.record(Stage::Build, start.elapsed());
+        frame_metrics.frame_built();
+
+        built
+    }
+}
+
+impl Runtime {
+    /// Creates the fallback fonts' instances at every registered size, so
+    /// that runs falling back to them can be drawn in the next frame. The
+    /// resource updates are taken before frames are built, which is too late
+    /// to add fonts. Runs matched to another face of their family need
+    /// nothing more, since they're drawn through the family's name, which
+    /// each of its instances was created with.
+    fn create_fallback_instances(&mut self) {
+        let font_family_name_map = FONT_FAMILY_NAME_MAP.lock().unwrap();
+        let font_size_map = FONT_SIZE_MAP.lock().unwrap();
+        let sizes = font_size_map.values().cloned().collect::<HashSet<_>>();
+        let family_names = self.fallback
+            .iter()
+            .filter_map(|key| font_family_name_map.get(key))
+            .collect::<HashSet<_>>();
+
+        let fonts = self.resources.fonts();
+        for family_name in family_names {
+            for &size in &sizes {
+                fonts.get_font_with_size(family_name.clone(), size);
+            }
+        }
+    }
+
+    /// Registers a font with the font cache and parses it for laying out
+    /// text runs, returning whether it could be read.
+    fn register_font(&mut self, font: &RemoteFontResource) -> bool {
+        // Fonts from local files are read through the file cache. Either
+        // way, they're also parsed on this side, for laying out text runs.
+        let (encoded, face) = match (&font.data_uri, &font.path) {
+            (&Some(ref data_uri), _) => (EncodedFont::from_data_uri(data_uri.clone()).unwrap(), Face::from_data_uri(data_uri)),
+            (&None, &Some(ref path)) => match self.read_file(path) {
+                Some(data) => (EncodedFont::from_bytes(data.clone()).unwrap(), Face::parse(data)),
+                None => {
+                    let path = path.display().to_string();
+                    log_event!(Level::Error, "resources", "Couldn't read font file", key = font.key, path = path);
+                    return false;
+                }
+            },
+            (&None, &None) => return false
+        };
+
+        let mut fonts = self.resources.fonts();
+        let font_name = format!("{}", font.key);
+        fonts.add_font(&font_name, &encoded, 0);
+
+        let mut font_family_name_map = FONT_FAMILY_NAME_MAP.lock().unwrap();
+        let family_name = fonts.get_family_name(font_name).unwrap();
+        log_event!(Level::Debug, "resources", "Font registered", key = font.key, family_name = family_name);
+        font_family_name_map.insert(font.key, family_name);
+        SHAPING_CACHE.lock().unwrap().invalidate_font(font.key);
+
+        // Text runs are matched to faces by these, rather than by family
+        // name, which every face in a family shares.
+        FONT_DESCRIPTORS.lock().unwrap().insert(font.key, font.descriptor(face.as_ref()));
+        match face {
+            Some(face) => {
+                FONT_FACES.lock().unwrap().insert(font.key, face);
+            }
+            None => log_event!(Level::Warn, "resources", "Couldn't read font metrics", key = font.key)
+        }
+        // Runs may be drawn with a different face, or fall back elsewhere,
+        // now that there's one more font.
+        TEXT_RUNS.lock().unwrap().clear();
+        true
+    }
+
+    /// Reads a local file through the file cache, so that a font added under
+    /// several keys is only read once.
+    fn read_file(&mut self, path: &Path) -> Option<Vec<u8>> {
+        let mut files = self.resources.files();
+        if files.get_file(path).is_none() {
+            files.add_file(path).ok()?;
+        }
+        files.get_file(path).map(|data| data.to_vec())
+    }
+}
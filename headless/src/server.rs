@@ -0,0 +1,113 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+use std::sync::Mutex;
+
+use serde_json;
+use ws::{CloseCode, Error, ErrorKind, Handler, Handshake, Message, Result, Sender};
+
+use logging::Level;
+use protocol::{receive_clear, receive_message, ProtocolError};
+use trace::{Recorder, TraceEvent};
+
+lazy_static! {
+    static ref RECORDER: Mutex<Option<Recorder>> = Default::default();
+}
+
+pub struct Server {
+    out: Sender
+}
+
+impl Server {
+    pub fn new(out: Sender) -> Server {
+        Server { out }
+    }
+}
+
+impl Handler for Server {
+    fn on_open(&mut self, shake: Handshake) -> Result<()> {
+        let peer = shake.peer_addr.map(|addr| addr.to_string());
+        log_event!(Level::Info, "connection", "Client connected", peer = peer);
+        Ok(())
+    }
+
+    fn on_message(&mut self, msg: Message) -> Result<()> {
+        let reply = match msg {
+            Message::Text(body) => {
+                let event = TraceEvent::Message(body);
+                let reply = dispatch(&event);
+                record(event);
+                reply
+            }
+            // Only JSON text messages are spoken. Binary frames aren't
+            // recorded, since traces can't hold them.
+            Message::Binary(_) => Some(reject(ProtocolError::BinaryMessage))
+        };
+
+        if let Some(reply) = reply {
+            self.out.send(reply.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    fn on_close(&mut self, code: CloseCode, reason: &str) {
+        log_event!(Level::Info, "connection", "Client disconnected", code = format!("{:?}", code), reason = reason);
+        let event = TraceEvent::Close;
+        dispatch(&event);
+        record(event);
+    }
+
+    fn on_error(&mut self, err: Error) {
+        log_event!(Level::Error, "connection", "Connection error", error = err.to_string());
+        // Text frames that aren't valid UTF-8 never make it to `on_message`,
+        // and the connection is closed after them, but the client is still
+        // told why before it is.
+        if let ErrorKind::Encoding(_) = err.kind {
+            let _ = self.out.send(reject(ProtocolError::InvalidUtf8).to_string());
+        }
+    }
+}
+
+/// Handles an event the same way regardless of whether it came from a client
+/// or from a trace being replayed, returning the reply to send back, if any.
+/// Messages that can't be handled get an `{ "error": .. }` reply.
+pub fn dispatch(event: &TraceEvent) -> Option<serde_json::Value> {
+    match event {
+        &TraceEvent::Message(ref body) => match receive_message(body) {
+            Ok(reply) => reply,
+            Err(err) => Some(reject(err))
+        },
+        &TraceEvent::Close => {
+            receive_clear();
+            None
+        }
+    }
+}
+
+/// The reply to a message that couldn't be handled.
+fn reject(err: ProtocolError) -> serde_json::Value {
+    log_event!(Level::Warn, "protocol", "Rejected message", error = err.to_string());
+    json!({ "error": err.to_string() })
+}
+
+/// Starts recording every event received by any server into `recorder`.
+pub fn record_to(recorder: Recorder) {
+    *RECORDER.lock().unwrap() = Some(recorder);
+}
+
+fn record(event: TraceEvent) {
+    if let Some(ref mut recorder) = *RECORDER.lock().unwrap() {
+        if let Err(err) = recorder.record(event) {
+            log_event!(Level::Error, "trace", "Couldn't record event", error = err.to_string());
+        }
+    }
+}
@@ -0,0 +1,372 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+//! A minimal reader for TrueType and OpenType fonts, covering the metrics
+//! the renderer needs to lay text out on its own: character to glyph
+//! mapping, horizontal advances, the vertical metrics text decorations
+//! are placed with, and the names and styles installed fonts are looked up
+//! by.
+//!
+//! The font backend only knows fonts once they're registered on the render
+//! side, and only shapes whole strings, while matching faces, splitting
+//! runs between fallback fonts and indexing installed fonts all happen
+//! before that, off the render thread. Those need a handful of tables, which
+//! is little enough to read here rather than depend on FreeType. Every read
+//! is bounds checked, so malformed or truncated fonts fail to parse instead
+//! of panicking.
+
+use std::fs::File;
+use std::io::Read;
+use std::ops::Range;
+use std::path::Path;
+
+use base64;
+
+#[derive(Debug, Clone)]
+pub struct Face {
+    data: Vec<u8>,
+    units_per_em: u16,
+    number_of_h_metrics: u16,
+    hmtx: Range<usize>,
+    cmap: Option<Cmap>,
+    metrics: VerticalMetrics,
+    names: Option<Range<usize>>,
+    style: Style
+}
+
+/// A font's vertical metrics in font units, with positions measured upwards
+/// from the baseline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VerticalMetrics {
+    pub ascender: i16,
+    pub descender: i16,
+    pub underline_position: i16,
+    pub underline_thickness: i16,
+    pub strikeout_position: i16,
+    pub strikeout_thickness: i16
+}
+
+const NAME_FAMILY: u16 = 1;
+const NAME_SUBFAMILY: u16 = 2;
+const NAME_TYPOGRAPHIC_FAMILY: u16 = 16;
+const NAME_TYPOGRAPHIC_SUBFAMILY: u16 = 17;
+
+/// The style a font describes itself as having, as OS/2 weight and width
+/// classes, and whether it's italic or oblique.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Style {
+    pub weight_class: u16,
+    pub width_class: u16,
+    pub italic: bool,
+    pub oblique: bool
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Cmap {
+    SegmentMapping(usize),
+    SegmentedCoverage(usize)
+}
+
+impl Face {
+    /// Parses a font file, or the first font in a collection.
+    pub fn parse(data: Vec<u8>) -> Option<Face> {
+        let start = if data.get(0..4) == Some(&b"ttcf"[..]) { read_u32(&data, 12)? as usize } else { 0 };
+
+        let head = find_table(&data, start, b"head")?;
+        let hhea = find_table(&data, start, b"hhea")?;
+        let hmtx = find_table(&data, start, b"hmtx")?;
+        let cmap = find_table(&data, start, b"cmap").and_then(|cmap| find_cmap(&data, cmap.start));
+        let names = find_table(&data, start, b"name");
+
+        let units_per_em = read_u16(&data, head.start + 18)?;
+        if units_per_em == 0 {
+            return None;
+        }
+        let number_of_h_metrics = read_u16(&data, hhea.start + 34)?;
+
+        // The underline and strikeout are in optional tables, which some
+        // fonts leave zeroed. Fonts without them get a line a twentieth of an
+        // em thick, a tenth of an em below the baseline or a quarter of one
+        // above it.
+        let post = find_table(&data, start, b"post");
+        let os2 = find_table(&data, start, b"OS/2");
+        let default_thickness = (units_per_em / 20) as i16;
+        let underline = post.and_then(|post| read_line(&data, post.start + 8, post.start + 10));
+        let strikeout = os2.as_ref().and_then(|os2| read_line(&data, os2.start + 28, os2.start + 26));
+        let metrics = VerticalMetrics {
+            ascender: read_i16(&data, hhea.start + 4)?,
+            descender: read_i16(&data, hhea.start + 6)?,
+            underline_position: underline.map_or(-(units_per_em as i16 / 10), |(position, _)| position),
+            underline_thickness: underline.map_or(default_thickness, |(_, thickness)| thickness),
+            strikeout_position: strikeout.map_or(units_per_em as i16 / 4, |(position, _)| position),
+            strikeout_thickness: strikeout.map_or(default_thickness, |(_, thickness)| thickness)
+        };
+
+        // Fonts without an OS/2 table only say whether they're bold or
+        // italic, in the header's style bits.
+        let mac_style = read_u16(&data, head.start + 44).unwrap_or(0);
+        let style = match os2 {
+            Some(ref os2) => {
+                let selection = read_u16(&data, os2.start + 62).unwrap_or(0);
+                Style {
+                    weight_class: read_u16(&data, os2.start + 4).unwrap_or(400),
+                    width_class: read_u16(&data, os2.start + 6).unwrap_or(5),
+                    italic: selection & 1 != 0,
+                    oblique: selection & 1 << 9 != 0
+                }
+            }
+            None => Style {
+                weight_class: if mac_style & 1 != 0 { 700 } else { 400 },
+                width_class: 5,
+                italic: mac_style & 2 != 0,
+                oblique: false
+            }
+        };
+
+        Some(Face {
+            data,
+            units_per_em,
+            number_of_h_metrics,
+            hmtx,
+            cmap,
+            metrics,
+            names,
+            style
+        })
+    }
+
+    /// Parses a font sent as a base64 data URI, the way clients add them.
+    pub fn from_data_uri(data_uri: &str) -> Option<Face> {
+        let encoded = data_uri.splitn(2, ',').nth(1)?;
+        Face::parse(base64::decode(encoded).ok()?)
+    }
+
+    /// Reads and parses a local font file.
+    pub fn from_path(path: &Path) -> Option<Face> {
+        let mut data = vec![];
+        File::open(path).and_then(|mut file| file.read_to_end(&mut data)).ok()?;
+        Face::parse(data)
+    }
+
+    pub fn units_per_em(&self) -> u16 {
+        self.units_per_em
+    }
+
+    pub fn vertical_metrics(&self) -> VerticalMetrics {
+        self.metrics
+    }
+
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
+    /// The font's family name, preferring the typographic family that groups
+    /// more than the usual four styles together.
+    pub fn family_name(&self) -> Option<String> {
+        self.name(NAME_TYPOGRAPHIC_FAMILY).or_else(|| self.name(NAME_FAMILY))
+    }
+
+    /// The style within the family, such as "Regular" or "Bold Italic".
+    pub fn subfamily_name(&self) -> Option<String> {
+        self.name(NAME_TYPOGRAPHIC_SUBFAMILY).or_else(|| self.name(NAME_SUBFAMILY))
+    }
+
+    /// Looks up an entry of the naming table, preferring American English
+    /// Unicode names, then any Unicode name, then Macintosh ones.
+    fn name(&self, id: u16) -> Option<String> {
+        let names = self.names.clone()?;
+        let count = read_u16(&self.data, names.start + 2)? as usize;
+        let strings = names.start + read_u16(&self.data, names.start + 4)? as usize;
+
+        let mut best: Option<(u8, String)> = None;
+        for i in 0..count {
+            let record = names.start + 6 + i * 12;
+            if read_u16(&self.data, record + 6)? != id {
+                continue;
+            }
+            let platform = read_u16(&self.data, record)?;
+            let language = read_u16(&self.data, record + 4)?;
+            let length = read_u16(&self.data, record + 8)? as usize;
+            let offset = strings + read_u16(&self.data, record + 10)? as usize;
+            let bytes = match self.data.get(offset..offset + length) {
+                Some(bytes) => bytes,
+                None => continue
+            };
+
+            let (rank, name) = match platform {
+                0 | 3 => {
+                    let units = bytes.chunks(2).filter(|unit| unit.len() == 2).map(|unit| (unit[0] as u16) << 8 | unit[1] as u16);
+                    let rank = if platform == 3 && language == 0x409 { 3 } else { 2 };
+                    (rank, String::from_utf16_lossy(&units.collect::<Vec<_>>()))
+                }
+                // Mac Roman, which only matches Latin-1 for ASCII, which is
+                // what names mostly are.
+                1 => (1, bytes.iter().map(|&byte| byte as char).collect()),
+                _ => continue
+            };
+            if best.as_ref().map_or(true, |&(best_rank, _)| rank > best_rank) {
+                best = Some((rank, name));
+            }
+        }
+        best.map(|(_, name)| name)
+    }
+
+    /// The glyph for `c`, or `None` if the font doesn't cover it.
+    pub fn glyph_index(&self, c: char) -> Option<u16> {
+        let glyph = match self.cmap? {
+            Cmap::SegmentMapping(offset) => segment_mapping_lookup(&self.data, offset, c as u32),
+            Cmap::SegmentedCoverage(offset) => segmented_coverage_lookup(&self.data, offset, c as u32)
+        };
+        match glyph {
+            Some(0) | None => None,
+            glyph => glyph
+        }
+    }
+
+    /// The advance of `glyph`, in font units.
+    pub fn glyph_advance(&self, glyph: u16) -> u16 {
+        if self.number_of_h_metrics == 0 {
+            return 0;
+        }
+        // Glyphs past the last metric share its advance.
+        let offset = self.hmtx.start + glyph.min(self.number_of_h_metrics - 1) as usize * 4;
+        if offset + 2 > self.hmtx.end {
+            return 0;
+        }
+        read_u16(&self.data, offset).unwrap_or(0)
+    }
+
+    /// The advance of `c` at `size` pixels. Characters the font doesn't
+    /// cover use the advance of its missing glyph.
+    pub fn char_width(&self, c: char, size: u32) -> f32 {
+        let glyph = self.glyph_index(c).unwrap_or(0);
+        self.scale(self.glyph_advance(glyph) as i32, size)
+    }
+
+    pub fn text_width(&self, text: &str, size: u32) -> f32 {
+        text.chars().map(|c| self.char_width(c, size)).sum()
+    }
+
+    /// Converts a distance in font units to pixels at `size`.
+    pub fn scale(&self, units: i32, size: u32) -> f32 {
+        units as f32 * size as f32 / self.units_per_em as f32
+    }
+}
+
+fn find_table(data: &[u8], start: usize, tag: &[u8; 4]) -> Option<Range<usize>> {
+    let count = read_u16(data, start + 4)? as usize;
+    for i in 0..count {
+        let record = start + 12 + i * 16;
+        if data.get(record..record + 4)? == &tag[..] {
+            let offset = read_u32(data, record + 8)? as usize;
+            let length = read_u32(data, record + 12)? as usize;
+            return if offset.checked_add(length)? <= data.len() { Some(offset..offset + length) } else { None };
+        }
+    }
+    None
+}
+
+/// Picks a Unicode subtable, preferring full coverage (format 12) over the
+/// Basic Multilingual Plane only (format 4).
+fn find_cmap(data: &[u8], cmap: usize) -> Option<Cmap> {
+    let count = read_u16(data, cmap + 2)? as usize;
+    let mut best = None;
+
+    for i in 0..count {
+        let record = cmap + 4 + i * 8;
+        let platform = read_u16(data, record)?;
+        let encoding = read_u16(data, record + 2)?;
+        let offset = cmap + read_u32(data, record + 4)? as usize;
+
+        let unicode = platform == 0 || (platform == 3 && (encoding == 1 || encoding == 10));
+        if !unicode {
+            continue;
+        }
+        match read_u16(data, offset) {
+            Some(12) => return Some(Cmap::SegmentedCoverage(offset)),
+            Some(4) => best = Some(Cmap::SegmentMapping(offset)),
+            _ => {}
+        }
+    }
+
+    best
+}
+
+fn segment_mapping_lookup(data: &[u8], offset: usize, c: u32) -> Option<u16> {
+    if c > 0xFFFF {
+        return None;
+    }
+    let c = c as u16;
+    let segments = read_u16(data, offset + 6)? as usize / 2;
+    let end_codes = offset + 14;
+    let start_codes = end_codes + segments * 2 + 2;
+    let id_deltas = start_codes + segments * 2;
+    let id_range_offsets = id_deltas + segments * 2;
+
+    for i in 0..segments {
+        if read_u16(data, end_codes + i * 2)? < c {
+            continue;
+        }
+        let start = read_u16(data, start_codes + i * 2)?;
+        if start > c {
+            return None;
+        }
+        let id_delta = read_u16(data, id_deltas + i * 2)?;
+        let id_range_offset = read_u16(data, id_range_offsets + i * 2)?;
+        if id_range_offset == 0 {
+            return Some(c.wrapping_add(id_delta));
+        }
+        let glyph_offset = id_range_offsets + i * 2 + id_range_offset as usize + (c - start) as usize * 2;
+        return match read_u16(data, glyph_offset)? {
+            0 => None,
+            glyph => Some(glyph.wrapping_add(id_delta))
+        };
+    }
+
+    None
+}
+
+fn segmented_coverage_lookup(data: &[u8], offset: usize, c: u32) -> Option<u16> {
+    let groups = read_u32(data, offset + 12)? as usize;
+    for i in 0..groups {
+        let group = offset + 16 + i * 12;
+        let start = read_u32(data, group)?;
+        let end = read_u32(data, group + 4)?;
+        if c >= start && c <= end {
+            return Some(read_u32(data, group + 8)?.wrapping_add(c - start) as u16);
+        }
+    }
+    None
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some((bytes[0] as u16) << 8 | bytes[1] as u16)
+}
+
+fn read_i16(data: &[u8], offset: usize) -> Option<i16> {
+    read_u16(data, offset).map(|value| value as i16)
+}
+
+/// Reads a decoration line's position and thickness, if it has any thickness.
+fn read_line(data: &[u8], position: usize, thickness: usize) -> Option<(i16, i16)> {
+    let thickness = read_i16(data, thickness)?;
+    if thickness > 0 {
+        Some((read_i16(data, position)?, thickness))
+    } else {
+        None
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some((bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 | bytes[3] as u32)
+}
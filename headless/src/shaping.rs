@@ -0,0 +1,282 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+//! Caching of text runs the client didn't shape itself, as the backend's
+//! shaper shaped them. Most text doesn't change from one frame to the next,
+//! so shaped runs are cached by font instance and text until they're evicted
+//! to stay within the cache's limits, or invalidated by the text or font
+//! changing.
+
+use std::collections::HashMap;
+use std::mem;
+
+use serde_json;
+
+use display_list::RemoteGlyph;
+
+pub const DEFAULT_MAX_ENTRIES: usize = 16 * 1024;
+pub const DEFAULT_MAX_BYTES: usize = 8 * 1024 * 1024;
+
+struct CacheEntry {
+    glyphs: Vec<RemoteGlyph>,
+    last_used: u64
+}
+
+impl CacheEntry {
+    fn bytes(&self, text: &str) -> usize {
+        text.len() + self.glyphs.len() * mem::size_of::<RemoteGlyph>()
+    }
+}
+
+/// Shaped runs keyed by `(font key, font instance key, right to left)` and
+/// then by text, so that lookups don't need to allocate a key. The least
+/// recently used runs are evicted once there are more than `max_entries` of
+/// them or they take more than `max_bytes`, except for those used since the
+/// current pass started, which are still to be drawn.
+pub struct ShapingCache {
+    entries: HashMap<(u64, u64, bool), HashMap<String, CacheEntry>>,
+    max_entries: usize,
+    max_bytes: usize,
+    len: usize,
+    bytes: usize,
+    clock: u64,
+    pass_start: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64
+}
+
+impl Default for ShapingCache {
+    fn default() -> Self {
+        ShapingCache::new(DEFAULT_MAX_ENTRIES, DEFAULT_MAX_BYTES)
+    }
+}
+
+impl ShapingCache {
+    pub fn new(max_entries: usize, max_bytes: usize) -> Self {
+        ShapingCache {
+            entries: HashMap::new(),
+            max_entries,
+            max_bytes,
+            len: 0,
+            bytes: 0,
+            clock: 0,
+            pass_start: 0,
+            hits: 0,
+            misses: 0,
+            evictions: 0
+        }
+    }
+
+    pub fn set_limits(&mut self, max_entries: usize, max_bytes: usize) {
+        self.max_entries = max_entries;
+        self.max_bytes = max_bytes;
+        self.evict();
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Starts a pass over the runs of an item, which are all shaped before
+    /// any of them is drawn. Runs used from here on aren't evicted until the
+    /// next pass starts, so shaping one of them can't evict another.
+    pub fn start_pass(&mut self) {
+        self.pass_start = self.clock;
+    }
+
+    /// The glyphs for `text` in the given font instance and direction, shaped
+    /// with `shape` unless they're already cached. Nothing is cached for text
+    /// `shape` can't shape.
+    pub fn shape<F>(&mut self, font_key: u64, font_instance_key: u64, rtl: bool, text: &str, shape: F) -> Option<&[RemoteGlyph]>
+    where
+        F: FnOnce() -> Option<Vec<RemoteGlyph>>
+    {
+        self.clock += 1;
+        let clock = self.clock;
+
+        let cached = self.entries
+            .get(&(font_key, font_instance_key, rtl))
+            .map_or(false, |texts| texts.contains_key(text));
+        if cached {
+            self.hits += 1;
+        } else {
+            let entry = CacheEntry {
+                glyphs: shape()?,
+                last_used: clock
+            };
+            self.misses += 1;
+            self.len += 1;
+            self.bytes += entry.bytes(text);
+            self.entries
+                .entry((font_key, font_instance_key, rtl))
+                .or_insert_with(HashMap::new)
+                .insert(text.to_string(), entry);
+            self.evict();
+        }
+
+        let entry = self.entries
+            .get_mut(&(font_key, font_instance_key, rtl))
+            .and_then(|texts| texts.get_mut(text))
+            .unwrap();
+        entry.last_used = clock;
+        Some(&entry.glyphs)
+    }
+
+    /// The glyphs for `text` in the given font instance and direction if
+    /// they're cached, without counting as a use.
+    pub fn get(&self, font_key: u64, font_instance_key: u64, rtl: bool, text: &str) -> Option<&[RemoteGlyph]> {
+        self.entries
+            .get(&(font_key, font_instance_key, rtl))
+            .and_then(|texts| texts.get(text))
+            .map(|entry| &entry.glyphs[..])
+    }
+
+    /// Forgets the runs for `text` in the given font instance, in either
+    /// direction, once an item showing it changes.
+    pub fn invalidate(&mut self, font_key: u64, font_instance_key: u64, text: &str) {
+        self.remove(&(font_key, font_instance_key, false), text);
+        self.remove(&(font_key, font_instance_key, true), text);
+    }
+
+    /// Forgets every run shaped with the font added as `font_key`, once it's
+    /// replaced.
+    pub fn invalidate_font(&mut self, font_key: u64) {
+        self.remove_instances(|&(key, _, _)| key == font_key);
+    }
+
+    /// Forgets every run shaped at the size of `font_instance_key`, once it's
+    /// replaced.
+    pub fn invalidate_font_instance(&mut self, font_instance_key: u64) {
+        self.remove_instances(|&(_, instance_key, _)| instance_key == font_instance_key);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.len = 0;
+        self.bytes = 0;
+    }
+
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64
+        }
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+        self.evictions = 0;
+    }
+
+    pub fn summary(&self) -> serde_json::Value {
+        json!({
+            "entries": self.len,
+            "bytes": self.bytes,
+            "hits": self.hits,
+            "misses": self.misses,
+            "evictions": self.evictions,
+            "hit_rate": self.hit_rate()
+        })
+    }
+
+    fn remove(&mut self, key: &(u64, u64, bool), text: &str) {
+        let removed = self.entries
+            .get_mut(key)
+            .and_then(|texts| texts.remove(text))
+            .map(|entry| entry.bytes(text));
+        if let Some(bytes) = removed {
+            self.len -= 1;
+            self.bytes -= bytes;
+        }
+    }
+
+    fn remove_instances<F>(&mut self, f: F)
+    where
+        F: Fn(&(u64, u64, bool)) -> bool
+    {
+        let keys = self.entries.keys().filter(|key| f(key)).cloned().collect::<Vec<_>>();
+        for key in keys {
+            for (text, entry) in self.entries.remove(&key).unwrap() {
+                self.len -= 1;
+                self.bytes -= entry.bytes(&text);
+            }
+        }
+    }
+
+    /// Evicts the least recently used runs once over either limit. It goes
+    /// down to three quarters of the limits so that a cache which is full
+    /// doesn't have to sort its entries on every miss.
+    fn evict(&mut self) {
+        if self.len <= self.max_entries && self.bytes <= self.max_bytes {
+            return;
+        }
+
+        let mut by_age = self.entries
+            .iter()
+            .flat_map(|(key, texts)| texts.iter().map(move |(text, entry)| (entry.last_used, *key, text.clone())))
+            .collect::<Vec<_>>();
+        by_age.sort_by_key(|&(last_used, _, _)| last_used);
+
+        let (max_entries, max_bytes) = (self.max_entries / 4 * 3, self.max_bytes / 4 * 3);
+        for (last_used, key, text) in by_age {
+            if self.len <= max_entries && self.bytes <= max_bytes {
+                break;
+            }
+            // Runs used in this pass, including the one just shaped, are
+            // about to be handed out.
+            if last_used > self.pass_start {
+                continue;
+            }
+            self.remove(&key, &text);
+            self.evictions += 1;
+        }
+        self.entries.retain(|_, texts| !texts.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shape(cache: &mut ShapingCache, text: &str) {
+        cache.shape(1, 2, false, text, || Some(vec![RemoteGlyph { index: 1, advance: 8.0 }; text.len()]));
+    }
+
+    #[test]
+    fn runs_used_in_the_current_pass_are_not_evicted() {
+        let mut cache = ShapingCache::new(4, DEFAULT_MAX_BYTES);
+        cache.start_pass();
+        for text in &["a", "b", "c", "d", "e"] {
+            shape(&mut cache, text);
+        }
+        assert_eq!(cache.len(), 5);
+        assert!(cache.get(1, 2, false, "a").is_some());
+
+        // Once the next pass starts, the least recently used runs go first.
+        cache.start_pass();
+        shape(&mut cache, "f");
+        assert_eq!(cache.len(), 3);
+        assert!(cache.get(1, 2, false, "a").is_none());
+        for text in &["d", "e", "f"] {
+            assert!(cache.get(1, 2, false, text).is_some());
+        }
+    }
+}
@@ -0,0 +1,260 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+
+use serde_json;
+
+use backend::{self, Fonts, SoftwareBackend, TRenderBackend};
+use display_list::RemoteDisplayItem;
+use font_fallback::{self, FallbackSpan, FontFallback};
+use font_matching::FontDescriptor;
+use logging::Level;
+use resources::RemoteResource;
+use sfnt::Face;
+use shaping::ShapingCache;
+use state::{
+    FONT_DESCRIPTORS,
+    FONT_FACES,
+    FONT_FALLBACK,
+    FONT_FAMILY_NAME_MAP,
+    FONT_SIZE_MAP,
+    REGISTERED_RESOURCES,
+    REMOTE_DISPLAY_LIST,
+    REMOTE_RESOURCES,
+    SHOULD_REDRAW,
+    SHOULD_SET_WINDOW_SIZE,
+    TEXT_RUNS,
+    WINDOW_SIZE
+};
+use text_runs::RunCache;
+
+/// Everything needed to render a frame without a client: the display list,
+/// the font resources it references (with their data embedded), the font
+/// lookup tables the renderer built from them and the fallback chain. Which
+/// text fell back to which font is included for debugging, but isn't needed
+/// to render.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub window_size: Option<(u32, u32)>,
+    pub display_list: Vec<RemoteDisplayItem>,
+    pub resources: Vec<RemoteResource>,
+    pub font_family_names: HashMap<u64, String>,
+    pub font_sizes: HashMap<u64, u32>,
+    #[serde(default)]
+    pub font_fallback: FontFallback,
+    #[serde(default)]
+    pub fallback_spans: Vec<FallbackSpan>
+}
+
+impl Snapshot {
+    pub fn capture() -> Snapshot {
+        let mut snapshot = {
+            // Locks are taken in the same order frames are built in, starting
+            // with the display list, or capturing while the debug overlay is
+            // drawn could deadlock.
+            let remote_display_list = REMOTE_DISPLAY_LIST.lock().unwrap();
+            let registered_resources = REGISTERED_RESOURCES.lock().unwrap();
+            let font_family_name_map = FONT_FAMILY_NAME_MAP.lock().unwrap();
+            let font_size_map = FONT_SIZE_MAP.lock().unwrap();
+            let font_faces = FONT_FACES.lock().unwrap();
+            let font_descriptors = FONT_DESCRIPTORS.lock().unwrap();
+            let fallback_fonts = FONT_FALLBACK.lock().unwrap().clone();
+            let fallback = fallback_fonts.chain();
+            let fonts = Fonts {
+                family_names: &font_family_name_map,
+                sizes: &font_size_map,
+                faces: &font_faces,
+                descriptors: &font_descriptors,
+                fallback: &fallback
+            };
+
+            Snapshot {
+                window_size: *WINDOW_SIZE.lock().unwrap(),
+                display_list: remote_display_list.clone(),
+                resources: registered_resources.clone(),
+                font_family_names: font_family_name_map.clone(),
+                font_sizes: font_size_map.clone(),
+                font_fallback: fallback_fonts,
+                fallback_spans: font_fallback::report(&remote_display_list, &fonts)
+            }
+        };
+        // Font files are only read once nothing is locked anymore, since
+        // large ones take a while.
+        snapshot.embed_fonts();
+        snapshot
+    }
+
+    /// Embeds the data of fonts added from local files, so that the snapshot
+    /// renders the same on machines which don't have them. Fonts whose files
+    /// can't be read anymore are left referring to them.
+    fn embed_fonts(&mut self) {
+        for resource in &mut self.resources {
+            if let &mut RemoteResource::Font(ref mut font) = resource {
+                if let Err(err) = font.embed() {
+                    log_event!(Level::Warn, "snapshot", "Couldn't embed font", key = font.key, error = err.to_string());
+                }
+            }
+        }
+    }
+
+    pub fn open<P>(path: P) -> io::Result<Snapshot>
+    where
+        P: AsRef<Path>
+    {
+        let file = File::open(path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn write<P>(&self, path: P) -> io::Result<()>
+    where
+        P: AsRef<Path>
+    {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// The size of the window the snapshot was taken in, or if unknown, the
+    /// smallest size that fits every display item.
+    pub fn frame_size(&self) -> (u32, u32) {
+        self.window_size.unwrap_or_else(|| {
+            self.display_list
+                .iter()
+                .filter_map(|item| item.rect())
+                .fold((0, 0), |(width, height), rect| {
+                    (
+                        width.max(rect.position.left.saturating_add(rect.size.width)),
+                        height.max(rect.position.top.saturating_add(rect.size.height))
+                    )
+                })
+        })
+    }
+
+    /// Parses the embedded fonts, for laying text out without a font backend.
+    pub fn font_faces(&self) -> HashMap<u64, Face> {
+        self.resources
+            .iter()
+            .filter_map(|resource| match resource {
+                &RemoteResource::Font(ref font) => font.face().map(|face| (font.key, face)),
+                _ => None
+            })
+            .collect()
+    }
+
+    /// What each font's face is like, from its resource or else from `faces`,
+    /// for matching text runs to faces.
+    pub fn font_descriptors(&self, faces: &HashMap<u64, Face>) -> HashMap<u64, FontDescriptor> {
+        self.resources
+            .iter()
+            .filter_map(|resource| match resource {
+                &RemoteResource::Font(ref font) => Some((font.key, font.descriptor(faces.get(&font.key)))),
+                _ => None
+            })
+            .collect()
+    }
+
+    /// Walks the display list into `backend`, laying text out with the
+    /// embedded fonts the same way the renderer does.
+    pub fn build<B>(&self, backend: &mut B)
+    where
+        B: TRenderBackend
+    {
+        let faces = self.font_faces();
+        let descriptors = self.font_descriptors(&faces);
+        let fallback = self.font_fallback.chain();
+        let fonts = Fonts {
+            family_names: &self.font_family_names,
+            sizes: &self.font_sizes,
+            faces: &faces,
+            descriptors: &descriptors,
+            fallback: &fallback
+        };
+        backend::build(backend, &self.display_list, fonts, &mut RunCache::default(), &mut ShapingCache::default());
+    }
+
+    /// Draws the snapshot on the CPU, without needing a GL context.
+    pub fn rasterize(&self) -> SoftwareBackend {
+        let (width, height) = self.frame_size();
+        let mut backend = SoftwareBackend::new(width, height);
+        self.build(&mut backend);
+        backend
+    }
+
+    /// Makes this snapshot the renderer's current state. Font resources are
+    /// queued for registration, so the lookup tables are rebuilt by the
+    /// runtime rather than trusted from the file. The global fallback chain
+    /// is the renderer's own, so only the session's is restored.
+    pub fn restore(self) {
+        let mut remote_resources = REMOTE_RESOURCES.lock().unwrap();
+        let mut remote_display_list = REMOTE_DISPLAY_LIST.lock().unwrap();
+
+        remote_resources.extend(self.resources);
+        *remote_display_list = self.display_list;
+        FONT_FALLBACK.lock().unwrap().session = self.font_fallback.session;
+        TEXT_RUNS.lock().unwrap().clear();
+
+        if let Some(size) = self.window_size {
+            *WINDOW_SIZE.lock().unwrap() = Some(size);
+            *SHOULD_SET_WINDOW_SIZE.lock().unwrap() = Some(size);
+        }
+
+        SHOULD_REDRAW.swap(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        serde_json::from_str(
+            r#"{
+                "window_size": [800, 600],
+                "display_list": [],
+                "resources": [
+                    { "Font": { "key": 1, "data_uri": "data:font/ttf;base64,AAEAAA==" } },
+                    { "FontInstance": { "key": 1, "instance_key": 2, "size": 16 } }
+                ],
+                "font_family_names": { "1": "Sans" },
+                "font_sizes": { "2": 16 }
+            }"#
+        ).unwrap()
+    }
+
+    #[test]
+    fn snapshots_are_written_and_opened_unchanged() {
+        let snapshot = snapshot();
+        let path = env::temp_dir().join("renderer-process-snapshot-test.json");
+        snapshot.write(&path).unwrap();
+        let opened = Snapshot::open(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(serde_json::to_value(&opened.unwrap()).unwrap(), serde_json::to_value(&snapshot).unwrap());
+    }
+
+    #[test]
+    fn missing_or_malformed_snapshots_are_errors() {
+        let path = env::temp_dir().join("renderer-process-malformed-snapshot-test.json");
+        File::create(&path).unwrap().write_all(b"{ \"window_size\": ").unwrap();
+        let malformed = Snapshot::open(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(malformed.is_err());
+        assert!(Snapshot::open(&path).is_err());
+    }
+}
@@ -0,0 +1,47 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+
+use display_list::RemoteDisplayItem;
+use font_fallback::FontFallback;
+use font_matching::FontDescriptor;
+use metrics::FrameMetrics;
+use resources::RemoteResource;
+use sfnt::Face;
+use shaping::ShapingCache;
+use system_fonts::SystemFonts;
+use text_runs::RunCache;
+
+lazy_static! {
+    pub static ref REMOTE_RESOURCES: Mutex<Vec<RemoteResource>> = Default::default();
+    pub static ref REGISTERED_RESOURCES: Mutex<Vec<RemoteResource>> = Default::default();
+    pub static ref REMOTE_DISPLAY_LIST: Mutex<Vec<RemoteDisplayItem>> = Default::default();
+    pub static ref FONT_FAMILY_NAME_MAP: Mutex<HashMap<u64, String>> = Default::default();
+    pub static ref FONT_SIZE_MAP: Mutex<HashMap<u64, u32>> = Default::default();
+    pub static ref FONT_FACES: Mutex<HashMap<u64, Face>> = Default::default();
+    pub static ref FONT_DESCRIPTORS: Mutex<HashMap<u64, FontDescriptor>> = Default::default();
+    pub static ref FONT_FALLBACK: Mutex<FontFallback> = Default::default();
+    pub static ref TEXT_RUNS: Mutex<RunCache> = Default::default();
+    pub static ref SHAPING_CACHE: Mutex<ShapingCache> = Default::default();
+    pub static ref SYSTEM_FONTS: Mutex<SystemFonts> = Default::default();
+    pub static ref FONT_DIRS: Mutex<Vec<PathBuf>> = Default::default();
+    pub static ref DUMP_DIR: Mutex<Option<PathBuf>> = Default::default();
+    pub static ref WINDOW_SIZE: Mutex<Option<(u32, u32)>> = Default::default();
+    pub static ref SHOULD_SET_WINDOW_POSITION: Mutex<Option<(i32, i32)>> = Default::default();
+    pub static ref SHOULD_SET_WINDOW_SIZE: Mutex<Option<(u32, u32)>> = Default::default();
+    pub static ref SHOULD_REDRAW: AtomicBool = Default::default();
+    pub static ref SHOW_OVERLAY: AtomicBool = Default::default();
+    pub static ref FRAME_METRICS: Mutex<FrameMetrics> = Default::default();
+}
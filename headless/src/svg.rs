@@ -0,0 +1,302 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+use std::fmt::Write;
+
+use rsx_primitives::rsx_layout::types::LayoutBoundingClientRect;
+use rsx_primitives::rsx_stylesheet::types::{BorderStyle, Color};
+
+use backend::{TRenderBackend, TextFont};
+use display_list::RemoteGlyph;
+use font_matching::FontStyle;
+use resources::RemoteResource;
+use snapshot::Snapshot;
+use util::color_components;
+
+/// Renders a snapshot as a standalone SVG document. Fonts are embedded as
+/// `@font-face` rules using the data URIs the client registered them with,
+/// or that the snapshot embedded fonts from local files as, so the output
+/// renders the same anywhere. Fonts whose files couldn't be read when the
+/// snapshot was taken are left out.
+///
+/// Text is laid out by the renderer, as it is for the other backends, so
+/// lines break, overflow, fall back between fonts and change direction where
+/// they would on screen. Each piece is then drawn as a `<text>` element in
+/// its box, leaving only the glyphs to the SVG renderer.
+pub fn export(snapshot: &Snapshot) -> String {
+    let (width, height) = snapshot.frame_size();
+    let mut svg = String::new();
+
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+        width,
+        height
+    ).unwrap();
+
+    // Text refers to fonts by family name, so the faces of a family are told
+    // apart by their descriptors.
+    let faces = snapshot.font_faces();
+    let descriptors = snapshot.font_descriptors(&faces);
+    svg.push_str("<style>\n");
+    for resource in &snapshot.resources {
+        if let &RemoteResource::Font(ref font) = resource {
+            if let (Some(data_uri), Some(family_name)) = (font.data_uri.as_ref(), snapshot.font_family_names.get(&font.key)) {
+                let descriptor = descriptors.get(&font.key).cloned().unwrap_or_default();
+                let style = match descriptor.style {
+                    FontStyle::Normal => "normal",
+                    FontStyle::Italic => "italic",
+                    FontStyle::Oblique => "oblique"
+                };
+                let rule = format!(
+                    r#"@font-face {{ font-family: {}; src: url("{}"); font-weight: {}; font-style: {}; font-stretch: {}%; }}"#,
+                    css_string(family_name),
+                    data_uri,
+                    descriptor.weight,
+                    style,
+                    descriptor.stretch
+                );
+                writeln!(svg, "{}", escape(&rule)).unwrap();
+            }
+        }
+    }
+    svg.push_str("</style>\n");
+
+    let mut backend = SvgBackend { svg };
+    snapshot.build(&mut backend);
+    let mut svg = backend.svg;
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+struct SvgBackend {
+    svg: String
+}
+
+impl TRenderBackend for SvgBackend {
+    fn push_rect(&mut self, rect: LayoutBoundingClientRect, color: Color) {
+        write_rect(&mut self.svg, rect, &color);
+    }
+
+    fn push_border(&mut self, rect: LayoutBoundingClientRect, widths: [u32; 4], colors: [Color; 4], styles: [BorderStyle; 4]) {
+        write_border(&mut self.svg, rect, widths, colors, styles);
+    }
+
+    fn push_text(&mut self, rect: LayoutBoundingClientRect, color: Color, font: TextFont, text: &str, rtl: bool) {
+        write_text(&mut self.svg, rect, &color, font, text, rtl, None);
+    }
+
+    /// SVG can't draw glyphs by index, so the text they were shaped from is
+    /// drawn instead, stretched to as wide as the glyphs are.
+    fn push_glyphs(&mut self, rect: LayoutBoundingClientRect, color: Color, font: TextFont, text: &str, glyphs: &[RemoteGlyph], rtl: bool) {
+        let width: f32 = glyphs.iter().map(|glyph| glyph.advance).sum();
+        write_text(&mut self.svg, rect, &color, font, text, rtl, Some(width));
+    }
+}
+
+fn write_rect(svg: &mut String, rect: LayoutBoundingClientRect, color: &Color) {
+    let LayoutBoundingClientRect { position, size, .. } = rect;
+    writeln!(
+        svg,
+        r#"<rect x="{}" y="{}" width="{}" height="{}" {}/>"#,
+        position.left,
+        position.top,
+        size.width,
+        size.height,
+        paint("fill", color)
+    ).unwrap();
+}
+
+fn write_border(svg: &mut String, rect: LayoutBoundingClientRect, widths: [u32; 4], colors: [Color; 4], styles: [BorderStyle; 4]) {
+    let LayoutBoundingClientRect { position, size, .. } = rect;
+    let (left, top) = (position.left as f32, position.top as f32);
+    let (right, bottom) = (left + size.width as f32, top + size.height as f32);
+
+    // Sides are in CSS order: top, right, bottom, left. Each stroke is inset
+    // by half its width so that it's drawn entirely inside the item's bounds.
+    for side in 0..4 {
+        let width = widths[side] as f32;
+        let inset = width / 2.0;
+        let (x1, y1, x2, y2) = match side {
+            0 => (left, top + inset, right, top + inset),
+            1 => (right - inset, top, right - inset, bottom),
+            2 => (left, bottom - inset, right, bottom - inset),
+            _ => (left + inset, top, left + inset, bottom)
+        };
+
+        let dash = match styles[side] {
+            BorderStyle::None | BorderStyle::Hidden => continue,
+            BorderStyle::Dashed => format!(r#" stroke-dasharray="{0} {0}""#, width * 3.0),
+            BorderStyle::Dotted => format!(r#" stroke-dasharray="0 {}" stroke-linecap="round""#, width * 2.0),
+            BorderStyle::Double => {
+                // Two lines a third of the width each, hugging the outer and inner edges.
+                let offset = width / 3.0;
+                let (dx, dy) = if side % 2 == 0 { (0.0, offset) } else { (offset, 0.0) };
+                for &shift in &[-1.0, 1.0] {
+                    write_line(
+                        svg,
+                        (x1 + shift * dx, y1 + shift * dy, x2 + shift * dx, y2 + shift * dy),
+                        offset,
+                        &colors[side],
+                        ""
+                    );
+                }
+                continue;
+            }
+            _ => String::new()
+        };
+
+        write_line(svg, (x1, y1, x2, y2), width, &colors[side], &dash);
+    }
+}
+
+fn write_line(svg: &mut String, (x1, y1, x2, y2): (f32, f32, f32, f32), width: f32, color: &Color, extra: &str) {
+    if width <= 0.0 {
+        return;
+    }
+    writeln!(
+        svg,
+        r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke-width="{}" {}{}/>"#,
+        x1,
+        y1,
+        x2,
+        y2,
+        width,
+        paint("stroke", color),
+        extra
+    ).unwrap();
+}
+
+/// Draws text in its box, centered across the line, which is where the
+/// renderer puts the baseline when the leading is split evenly. Right to
+/// left text starts from the box's right edge.
+fn write_text(svg: &mut String, rect: LayoutBoundingClientRect, color: &Color, font: TextFont, text: &str, rtl: bool, width: Option<f32>) {
+    let LayoutBoundingClientRect { position, size, .. } = rect;
+    let x = if rtl { position.left + size.width } else { position.left };
+    write!(
+        svg,
+        r#"<text x="{}" y="{}" dominant-baseline="central" xml:space="preserve" font-family="{}" font-size="{}" {}"#,
+        x,
+        position.top as f32 + size.height as f32 / 2.0,
+        escape(&css_string(font.family_name)),
+        font.size,
+        paint("fill", color)
+    ).unwrap();
+    if rtl {
+        svg.push_str(r#" direction="rtl" unicode-bidi="embed""#);
+    }
+    if let Some(width) = width {
+        write!(svg, r#" textLength="{}" lengthAdjust="spacingAndGlyphs""#, width).unwrap();
+    }
+    writeln!(svg, ">{}</text>", escape(text)).unwrap();
+}
+
+fn paint(attribute: &str, color: &Color) -> String {
+    let components = color_components(color);
+    format!(
+        r#"{0}="rgb({1},{2},{3})" {0}-opacity="{4}""#,
+        attribute,
+        components[0],
+        components[1],
+        components[2],
+        components[3] as f32 / 255.0
+    )
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+/// Quotes a font family name for CSS.
+fn css_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use display_list::{RemoteDisplayItem, RemoteRectItem, RemoteTextItem, RemoteTextLayout, TextAlign};
+
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        let mut snapshot: Snapshot = serde_json::from_str(
+            r#"{
+                "window_size": [800, 600],
+                "display_list": [],
+                "resources": [{ "Font": { "key": 1, "data_uri": "data:font/ttf;base64,AAEAAA==" } }],
+                "font_family_names": { "1": "Sans" },
+                "font_sizes": { "2": 16 }
+            }"#
+        ).unwrap();
+        snapshot.display_list.push(RemoteDisplayItem::Rect(RemoteRectItem {
+            rect: LayoutBoundingClientRect::new(10, 20, 100, 50),
+            color: Color::new([255, 0, 0, 255])
+        }));
+        snapshot
+    }
+
+    #[test]
+    fn exports_are_standalone_documents() {
+        let document = export(&snapshot());
+        assert!(document.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="800" height="600" viewBox="0 0 800 600">"#));
+        assert!(document.ends_with("</svg>\n"));
+        assert!(document.contains("@font-face { font-family: &quot;Sans&quot;; src: url(&quot;data:font/ttf;base64,AAEAAA==&quot;); font-weight: 400; font-style: normal; font-stretch: 100%; }"));
+        assert!(document.contains(r#"<rect x="10" y="20" width="100" height="50" fill="rgb(255,0,0)" fill-opacity="1"/>"#));
+    }
+
+    #[test]
+    fn text_is_escaped() {
+        assert_eq!(escape(r#"a<b & "c">"#), "a&lt;b &amp; &quot;c&quot;&gt;");
+    }
+
+    #[test]
+    fn text_is_laid_out_like_the_renderer() {
+        let mut snapshot = snapshot();
+        let rect = LayoutBoundingClientRect::new(10, 20, 100, 50);
+        let red = Color::new([255, 0, 0, 255]);
+        let layout = RemoteTextLayout {
+            wrap: true,
+            line_height: Some(20.0),
+            align: TextAlign::Right,
+            ..Default::default()
+        };
+        snapshot.display_list.push(RemoteDisplayItem::Text(RemoteTextItem::new(rect, red, "aaaa bbbb cccc\ndd", layout)));
+        snapshot.display_list.push(RemoteDisplayItem::Text(RemoteTextItem::new(rect, red, "a<b \u{5d0}\u{5d1}", Default::default())));
+        let document = export(&snapshot);
+
+        // Text is broken into the same lines and pieces as when it's
+        // rendered, each drawn in its own box.
+        let texts = document.lines().filter(|line| line.starts_with("<text")).collect::<Vec<_>>();
+        let contents = texts
+            .iter()
+            .map(|line| &line[line.find('>').unwrap() + 1..line.rfind("</text>").unwrap()])
+            .collect::<Vec<_>>();
+        assert_eq!(contents, vec!["aaaa bbbb ", "cccc", "dd", "a&lt;b ", "\u{5d0}\u{5d1}"]);
+        assert!(texts[0].starts_with(r#"<text x="38" y="30""#));
+        assert!(texts[2].starts_with(r#"<text x="94" y="70""#));
+        assert!(!texts[3].contains("direction"));
+        // Right to left text starts from the right edge of its box.
+        assert!(texts[4].starts_with(r#"<text x="58""#) && texts[4].contains(r#"direction="rtl""#));
+    }
+}
@@ -0,0 +1,176 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+//! Finds installed fonts by family name, in the directories fontconfig is
+//! configured to look in, so that clients can use them without sending them
+//! over the socket.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use font_matching::{self, FontDescriptor};
+use sfnt::Face;
+
+// Fontconfig's configuration, which lists the font directories in `<dir>`
+// elements, either in the main file or in the snippets it includes.
+const CONFIG_FILE: &str = "/etc/fonts/fonts.conf";
+const CONFIG_DIR: &str = "/etc/fonts/conf.d";
+
+// Searched as well, for systems where fontconfig isn't set up.
+const DEFAULT_DIRS: &[&str] = &["/usr/share/fonts", "/usr/local/share/fonts", "~/.local/share/fonts", "~/.fonts"];
+
+const FONT_EXTENSIONS: &[&str] = &["ttf", "otf", "ttc"];
+
+/// Installed font files and what their faces are like, by lowercased family
+/// name. Reading every installed font takes a while, so the renderer does it
+/// once as it starts, or failing that, it happens on the first lookup.
+#[derive(Debug, Default)]
+pub struct SystemFonts {
+    dirs: Option<Vec<PathBuf>>,
+    families: Option<HashMap<String, Vec<(PathBuf, FontDescriptor)>>>
+}
+
+impl SystemFonts {
+    /// Looks fonts up in `dirs` rather than in fontconfig's directories.
+    pub fn set_dirs(&mut self, dirs: Vec<PathBuf>) {
+        self.dirs = Some(dirs);
+        self.families = None;
+    }
+
+    /// Reads every installed font, unless that was already done.
+    pub fn load(&mut self) {
+        if self.families.is_none() {
+            let dirs = self.dirs.clone().unwrap_or_else(font_dirs);
+            self.families = Some(scan(&dirs));
+        }
+    }
+
+    /// The file of the face of the family named `family`, ignoring case,
+    /// that best matches `wanted`.
+    pub fn find(&mut self, family: &str, wanted: &FontDescriptor) -> Option<PathBuf> {
+        self.load();
+        let faces = self.families.as_ref()?.get(&family.to_lowercase())?;
+        let candidates = faces.iter().enumerate().map(|(i, &(_, descriptor))| (i, descriptor)).collect::<Vec<_>>();
+        let best = font_matching::best_match(&candidates, wanted)?;
+        Some(faces[best].0.clone())
+    }
+}
+
+/// The font directories fontconfig is configured with, along with the usual
+/// ones, leaving out those that don't exist.
+pub fn font_dirs() -> Vec<PathBuf> {
+    let mut configs = vec![PathBuf::from(CONFIG_FILE)];
+    if let Ok(entries) = fs::read_dir(CONFIG_DIR) {
+        let mut snippets = entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect::<Vec<_>>();
+        snippets.sort();
+        configs.extend(snippets);
+    }
+
+    let mut dirs = vec![];
+    for config in configs {
+        let mut contents = String::new();
+        if File::open(&config).and_then(|mut file| file.read_to_string(&mut contents)).is_ok() {
+            dirs.extend(config_dirs(&contents));
+        }
+    }
+    dirs.extend(DEFAULT_DIRS.iter().filter_map(|dir| expand(dir, None)));
+
+    let mut unique: Vec<PathBuf> = vec![];
+    for dir in dirs {
+        if dir.is_dir() && !unique.contains(&dir) {
+            unique.push(dir);
+        }
+    }
+    unique
+}
+
+/// The directories in a fontconfig configuration's `<dir>` elements. Only
+/// the `xdg` prefix is supported, which makes the path relative to the XDG
+/// data directory.
+fn config_dirs(config: &str) -> Vec<PathBuf> {
+    let mut dirs = vec![];
+    let mut rest = config;
+    while let Some(start) = rest.find("<dir") {
+        rest = &rest[start + 4..];
+        let (attributes, body) = match (rest.find('>'), rest.find("</dir>")) {
+            (Some(open), Some(close)) if open < close => (&rest[..open], &rest[open + 1..close]),
+            _ => continue
+        };
+        // Self closing and `<directory>` like elements aren't directories.
+        if attributes.ends_with('/') || !(attributes.is_empty() || attributes.starts_with(char::is_whitespace)) {
+            continue;
+        }
+        let prefix = if attributes.contains("prefix=\"xdg\"") { Some("xdg") } else { None };
+        dirs.extend(expand(body.trim(), prefix));
+    }
+    dirs
+}
+
+fn expand(dir: &str, prefix: Option<&str>) -> Option<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    if prefix == Some("xdg") {
+        let data_home = env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home.map(|home| home.join(".local/share")))?;
+        return Some(data_home.join(dir));
+    }
+    if dir == "~" {
+        return home;
+    }
+    if dir.starts_with("~/") {
+        return Some(home?.join(&dir[2..]));
+    }
+    Some(PathBuf::from(dir))
+}
+
+/// Reads every font under `dirs`, in a stable order so that the same font
+/// wins between runs when faces are installed more than once.
+fn scan(dirs: &[PathBuf]) -> HashMap<String, Vec<(PathBuf, FontDescriptor)>> {
+    let mut files = vec![];
+    for dir in dirs {
+        collect_fonts(dir, &mut files);
+    }
+    files.sort();
+
+    let mut families: HashMap<String, Vec<(PathBuf, FontDescriptor)>> = HashMap::new();
+    for path in files {
+        let face = match Face::from_path(&path) {
+            Some(face) => face,
+            None => continue
+        };
+        if let Some(family) = face.family_name() {
+            let descriptor = FontDescriptor::from_face(&face);
+            families.entry(family.to_lowercase()).or_insert_with(Vec::new).push((path, descriptor));
+        }
+    }
+    families
+}
+
+fn collect_fonts(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_fonts(&path, files);
+        } else if path.extension()
+            .and_then(|extension| extension.to_str())
+            .map_or(false, |extension| FONT_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+        {
+            files.push(path);
+        }
+    }
+}
@@ -0,0 +1,679 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+//! Breaks a text item's runs into lines within its bounds, and orders each
+//! line's fragments for display.
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use unicode_bidi::{bidi_class, BidiClass, BidiInfo, Level};
+
+use backend::TextFont;
+use display_list::{RemoteGlyph, RemoteTextItem, RemoteTextRun, TextAlign, TextDirection, TextOrientation, TextOverflow};
+use sfnt::Face;
+
+/// What lines that don't fit are ended with, or cut in the middle with.
+pub const ELLIPSIS: &str = "\u{2026}";
+
+// Lines are this many times as tall as the largest font on them when the
+// item doesn't say otherwise.
+const DEFAULT_LINE_HEIGHT_RATIO: f32 = 1.2;
+
+// Rough glyph advance relative to the font size, for fonts that couldn't be
+// parsed and so whose metrics aren't known on this side.
+pub const FALLBACK_ADVANCE_RATIO: f32 = 0.5;
+
+/// A run ready to be laid out, along with the face matched for it, its
+/// glyphs if it could be shaped, and those of an ellipsis in its font if the
+/// item might need one.
+pub struct ShapedRun<'a> {
+    pub run: &'a RemoteTextRun,
+    pub font: TextFont<'a>,
+    pub face: Option<&'a Face>,
+    pub glyphs: Option<&'a [RemoteGlyph]>,
+    pub ellipsis: Option<&'a [RemoteGlyph]>
+}
+
+/// The part of a run which ends up on a given line, or in vertical text, a
+/// single character of it. Text and glyphs are in logical order, so right
+/// to left fragments have to be drawn back to front. Positions are relative
+/// to the item's origin.
+pub struct Fragment<'a> {
+    pub run: &'a RemoteTextRun,
+    pub font: TextFont<'a>,
+    pub face: Option<&'a Face>,
+    pub text: &'a str,
+    pub glyphs: Option<&'a [RemoteGlyph]>,
+    pub rtl: bool,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32
+}
+
+/// A line of text, which is a column in vertical text. Its box only covers
+/// its content, not counting trailing spaces.
+pub struct Line<'a> {
+    pub fragments: Vec<Fragment<'a>>,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32
+}
+
+#[derive(Clone, PartialEq)]
+enum ClusterKind {
+    Newline,
+    Space,
+    Ellipsis,
+    Other
+}
+
+/// The smallest piece of text lines are broken around. That's a character,
+/// or a whole run when the client's glyphs don't map to its characters one
+/// to one, as happens with ligatures. The advance is horizontal, while the
+/// extent is along the line, which differs for vertical text. An ellipsis
+/// takes its run's ellipsis glyphs, and no bytes of the text.
+#[derive(Clone)]
+struct Cluster {
+    run: usize,
+    bytes: Range<usize>,
+    glyphs: Option<Range<usize>>,
+    advance: f32,
+    extent: f32,
+    level: u8,
+    kind: ClusterKind
+}
+
+/// Consecutive clusters of a line from the same run and at the same bidi
+/// level, or an ellipsis on its own.
+struct Span {
+    run: usize,
+    bytes: Range<usize>,
+    glyphs: Option<Range<usize>>,
+    advance: f32,
+    extent: f32,
+    level: u8,
+    ellipsis: bool
+}
+
+/// Lays out `runs`, which are `item`'s runs that can be drawn, following the
+/// item's layout options.
+pub fn lay_out<'a>(item: &'a RemoteTextItem, runs: &[ShapedRun<'a>]) -> Vec<Line<'a>> {
+    let vertical = item.layout.orientation == TextOrientation::Vertical;
+    // Vertical text is meant for CJK, which has no use for bidi.
+    let (levels, base_level) = if vertical { (None, 0) } else { bidi_levels(item) };
+    let clusters = clusters(item, runs, levels.as_ref().map(|levels| &levels[..]), vertical);
+
+    let (max_extent, max_cross) = if vertical {
+        (item.rect.size.height as f32, item.rect.size.width as f32)
+    } else {
+        (item.rect.size.width as f32, item.rect.size.height as f32)
+    };
+    let line_height = match item.layout.line_height {
+        Some(line_height) => line_height,
+        None => runs.iter().map(|shaped| shaped.font.size).max().unwrap_or(0) as f32 * DEFAULT_LINE_HEIGHT_RATIO
+    };
+
+    // Lines which don't fit are dropped unless overflow is visible, though
+    // the first one is always kept.
+    let overflow = item.layout.overflow;
+    let ranges = break_lines(&clusters, item.layout.wrap, max_extent);
+    let line_count = if overflow != TextOverflow::Visible && line_height > 0.0 {
+        ((max_cross / line_height) as usize).max(1).min(ranges.len())
+    } else {
+        ranges.len()
+    };
+    let clamped = line_count < ranges.len() && overflow != TextOverflow::Clip;
+
+    let mut lines = vec![];
+    for (i, range) in ranges.into_iter().take(line_count).enumerate() {
+        let truncated;
+        let clusters = if overflow == TextOverflow::Visible {
+            &clusters[range]
+        } else {
+            truncated = truncate(&clusters[range], runs, overflow, max_extent, clamped && i + 1 == line_count, vertical);
+            &truncated[..]
+        };
+        let trailing_spaces = clusters.iter().rev().take_while(|cluster| cluster.kind == ClusterKind::Space).count();
+        let trailing_extent = clusters[clusters.len() - trailing_spaces..].iter().map(|cluster| cluster.extent).sum::<f32>();
+        let extent = clusters.iter().map(|cluster| cluster.extent).sum::<f32>() - trailing_extent;
+
+        let offset = match item.layout.align {
+            TextAlign::Left => 0.0,
+            TextAlign::Center => ((max_extent - extent) / 2.0).max(0.0),
+            TextAlign::Right => (max_extent - extent).max(0.0)
+        };
+
+        // Trailing spaces take the paragraph's direction, which puts them
+        // before the content in right to left paragraphs.
+        let mut spans = spans(clusters, clusters.len() - trailing_spaces, base_level, vertical);
+        reorder(&mut spans);
+        let mut position = if base_level % 2 == 1 { (offset - trailing_extent).max(0.0) } else { offset };
+
+        let line = if vertical {
+            // Columns go from right to left, with every character upright and
+            // centered in its column.
+            let x = max_cross - (i + 1) as f32 * line_height;
+            let fragments = spans
+                .into_iter()
+                .map(|span| {
+                    let fragment = fragment(item, runs, &span, x + (line_height - span.advance) / 2.0, position);
+                    position += span.extent;
+                    Fragment {
+                        width: span.advance,
+                        height: span.extent,
+                        ..fragment
+                    }
+                })
+                .collect();
+            Line {
+                fragments,
+                x,
+                y: offset,
+                width: line_height,
+                height: extent
+            }
+        } else {
+            let y = i as f32 * line_height;
+            let fragments = spans
+                .into_iter()
+                .map(|span| {
+                    let fragment = fragment(item, runs, &span, position, y);
+                    position += span.extent;
+                    Fragment {
+                        width: span.extent,
+                        height: line_height,
+                        ..fragment
+                    }
+                })
+                .collect();
+            Line {
+                fragments,
+                x: offset,
+                y,
+                width: extent,
+                height: line_height
+            }
+        };
+        lines.push(line);
+    }
+    lines
+}
+
+/// The bidi embedding level of every byte of the item's text, and the level
+/// of its first paragraph. Left to right text without any strong right to
+/// left characters, which is most of it, skips the bidi algorithm entirely.
+pub fn bidi_levels(item: &RemoteTextItem) -> (Option<Vec<Level>>, u8) {
+    let default_level = match item.layout.direction {
+        TextDirection::Ltr => Some(Level::ltr()),
+        TextDirection::Rtl => Some(Level::rtl()),
+        TextDirection::Auto => None
+    };
+    let has_rtl = item.text.chars().any(|c| match bidi_class(c) {
+        BidiClass::R | BidiClass::AL | BidiClass::RLE | BidiClass::RLO | BidiClass::RLI => true,
+        _ => false
+    });
+    if default_level == Some(Level::ltr()) && !has_rtl {
+        return (None, 0);
+    }
+
+    let bidi_info = BidiInfo::new(&item.text, default_level);
+    let base_level = bidi_info.paragraphs.first().map_or(0, |paragraph| paragraph.level.number());
+    (Some(bidi_info.levels), base_level)
+}
+
+/// Splits `run` where its text changes direction, so that each piece can be
+/// shaped in a single direction, telling which pieces are right to left.
+/// `levels` come from `bidi_levels`. Runs the client shaped are left whole,
+/// since their glyphs go with all of their text.
+pub fn split_by_direction<'a>(item: &RemoteTextItem, run: Cow<'a, RemoteTextRun>, levels: Option<&[Level]>) -> Vec<(Cow<'a, RemoteTextRun>, bool)> {
+    let rtl = |byte: usize| levels.and_then(|levels| levels.get(byte)).map_or(false, |level| level.number() % 2 == 1);
+    let mut starts = vec![run.start];
+    if run.glyphs.is_none() {
+        for (offset, _) in item.run_text(&run).char_indices() {
+            if rtl(run.start + offset) != rtl(*starts.last().unwrap()) {
+                starts.push(run.start + offset);
+            }
+        }
+    }
+    if starts.len() == 1 {
+        let rtl = rtl(run.start);
+        return vec![(run, rtl)];
+    }
+
+    let ends = starts.iter().skip(1).cloned().chain(Some(run.end)).collect::<Vec<_>>();
+    starts
+        .iter()
+        .zip(ends)
+        .map(|(&start, end)| {
+            let piece = RemoteTextRun {
+                start,
+                end,
+                ..(*run).clone()
+            };
+            (Cow::Owned(piece), rtl(start))
+        })
+        .collect()
+}
+
+fn clusters(item: &RemoteTextItem, runs: &[ShapedRun], levels: Option<&[Level]>, vertical: bool) -> Vec<Cluster> {
+    let level = |byte: usize| levels.and_then(|levels| levels.get(byte)).map_or(0, |level| level.number());
+    let mut clusters = vec![];
+    for (i, shaped) in runs.iter().enumerate() {
+        let start = shaped.run.start;
+        let size = shaped.font.size as f32;
+        let text = item.run_text(shaped.run);
+        match shaped.glyphs {
+            Some(glyphs) if glyphs.len() != text.chars().count() => {
+                let advance = glyphs.iter().map(|glyph| glyph.advance).sum();
+                clusters.push(Cluster {
+                    run: i,
+                    bytes: start..start + text.len(),
+                    glyphs: Some(0..glyphs.len()),
+                    advance,
+                    extent: if vertical { size * glyphs.len() as f32 } else { advance },
+                    level: level(start),
+                    kind: ClusterKind::Other
+                });
+            }
+            glyphs => {
+                for (j, (offset, c)) in text.char_indices().enumerate() {
+                    let kind = match c {
+                        '\n' => ClusterKind::Newline,
+                        c if c.is_whitespace() => ClusterKind::Space,
+                        _ => ClusterKind::Other
+                    };
+                    let advance = match (glyphs, &kind) {
+                        (_, &ClusterKind::Newline) => 0.0,
+                        (Some(glyphs), _) => glyphs[j].advance,
+                        (None, _) => size * FALLBACK_ADVANCE_RATIO
+                    };
+                    let extent = match kind {
+                        ClusterKind::Newline => 0.0,
+                        _ if vertical => size,
+                        _ => advance
+                    };
+                    clusters.push(Cluster {
+                        run: i,
+                        bytes: start + offset..start + offset + c.len_utf8(),
+                        glyphs: glyphs.map(|_| j..j + 1),
+                        advance,
+                        extent,
+                        level: level(start + offset),
+                        kind
+                    });
+                }
+            }
+        }
+    }
+    clusters
+}
+
+/// Splits `clusters` into lines at newlines and, when wrapping, after the
+/// last space that lets the line fit in `max_extent`. Words which don't fit
+/// on a line of their own are broken wherever they overflow.
+fn break_lines(clusters: &[Cluster], wrap: bool, max_extent: f32) -> Vec<Range<usize>> {
+    let mut lines = vec![];
+    let mut start = 0;
+    let mut extent: f32 = 0.0;
+    let mut last_break = None;
+
+    let mut i = 0;
+    while i < clusters.len() {
+        let cluster = &clusters[i];
+        if cluster.kind == ClusterKind::Newline {
+            lines.push(start..i);
+            start = i + 1;
+            extent = 0.0;
+            last_break = None;
+        } else if wrap && cluster.kind == ClusterKind::Other && i > start && extent + cluster.extent > max_extent {
+            let end = last_break.unwrap_or(i);
+            lines.push(start..end);
+            start = end;
+            extent = clusters[start..i].iter().map(|cluster| cluster.extent).sum();
+            last_break = None;
+            // Whatever's left of the word moved to the new line, so the
+            // current cluster has to be checked against it again.
+            continue;
+        } else {
+            extent += cluster.extent;
+            if cluster.kind == ClusterKind::Space {
+                last_break = Some(i + 1);
+            }
+        }
+        i += 1;
+    }
+
+    lines.push(start..clusters.len());
+    lines
+}
+
+/// Shortens a line longer than `max_extent` following `overflow`, dropping
+/// trailing spaces along with whatever else doesn't fit. A `clamped` line is
+/// the last one kept out of more, and gets an ellipsis at its end whether
+/// it fits or not.
+fn truncate(
+    clusters: &[Cluster],
+    runs: &[ShapedRun],
+    overflow: TextOverflow,
+    max_extent: f32,
+    clamped: bool,
+    vertical: bool
+) -> Vec<Cluster> {
+    let trailing_spaces = clusters.iter().rev().take_while(|cluster| cluster.kind == ClusterKind::Space).count();
+    let content = &clusters[..clusters.len() - trailing_spaces];
+    if content.is_empty() || (!clamped && content.iter().map(|cluster| cluster.extent).sum::<f32>() <= max_extent) {
+        return clusters.to_vec();
+    }
+
+    match overflow {
+        TextOverflow::Visible => clusters.to_vec(),
+        TextOverflow::Clip => content[..fitting(content, max_extent)].to_vec(),
+        TextOverflow::Ellipsis | TextOverflow::EllipsisMiddle if clamped || overflow == TextOverflow::Ellipsis => {
+            let last = &content[content.len() - 1];
+            let kept = fitting(content, max_extent - ellipsis(runs, last, vertical).extent);
+            let mut line = content[..kept].to_vec();
+            line.push(ellipsis(runs, content.get(kept).unwrap_or(last), vertical));
+            line
+        }
+        TextOverflow::Ellipsis | TextOverflow::EllipsisMiddle => {
+            // Clusters are kept from both ends in turn, favoring whichever
+            // end has less so far.
+            let available = max_extent - ellipsis(runs, &content[0], vertical).extent;
+            let (mut head, mut tail) = (0, content.len());
+            let (mut head_extent, mut tail_extent) = (0.0, 0.0);
+            while head < tail {
+                let from_head = head_extent <= tail_extent;
+                let extent = if from_head { content[head].extent } else { content[tail - 1].extent };
+                if head_extent + tail_extent + extent > available {
+                    break;
+                }
+                if from_head {
+                    head += 1;
+                    head_extent += extent;
+                } else {
+                    tail -= 1;
+                    tail_extent += extent;
+                }
+            }
+            let mut line = content[..head].to_vec();
+            line.push(ellipsis(runs, &content[head], vertical));
+            line.extend_from_slice(&content[tail..]);
+            line
+        }
+    }
+}
+
+/// How many of `clusters`, from the first, fit in `max_extent`.
+fn fitting(clusters: &[Cluster], max_extent: f32) -> usize {
+    let mut extent = 0.0;
+    clusters
+        .iter()
+        .take_while(|cluster| {
+            extent += cluster.extent;
+            extent <= max_extent
+        })
+        .count()
+}
+
+/// An ellipsis in the font and at the bidi level of `replaced`, which is the
+/// first cluster it stands in for.
+fn ellipsis(runs: &[ShapedRun], replaced: &Cluster, vertical: bool) -> Cluster {
+    let shaped = &runs[replaced.run];
+    let size = shaped.font.size as f32;
+    let advance = match shaped.ellipsis {
+        Some(glyphs) => glyphs.iter().map(|glyph| glyph.advance).sum(),
+        None => size * FALLBACK_ADVANCE_RATIO
+    };
+    Cluster {
+        run: replaced.run,
+        bytes: replaced.bytes.start..replaced.bytes.start,
+        glyphs: shaped.ellipsis.map(|glyphs| 0..glyphs.len()),
+        advance,
+        extent: if vertical { size } else { advance },
+        level: replaced.level,
+        kind: ClusterKind::Ellipsis
+    }
+}
+
+/// Groups a line's clusters into spans, in logical order. Clusters from
+/// `trailing` onwards are trailing spaces, which take `base_level`. Vertical
+/// text gets a span per cluster, since each one is positioned on its own.
+fn spans(clusters: &[Cluster], trailing: usize, base_level: u8, vertical: bool) -> Vec<Span> {
+    let mut spans: Vec<Span> = vec![];
+    for (i, cluster) in clusters.iter().enumerate() {
+        let level = if i < trailing { cluster.level } else { base_level };
+        let ellipsis = cluster.kind == ClusterKind::Ellipsis;
+        let extends = !vertical && !ellipsis && spans
+            .last()
+            .map_or(false, |span| span.run == cluster.run && span.level == level && !span.ellipsis);
+        if extends {
+            let span = spans.last_mut().unwrap();
+            span.bytes.end = cluster.bytes.end;
+            span.glyphs = match (span.glyphs.clone(), cluster.glyphs.clone()) {
+                (Some(glyphs), Some(next)) => Some(glyphs.start..next.end),
+                _ => None
+            };
+            span.advance += cluster.advance;
+            span.extent += cluster.extent;
+        } else {
+            spans.push(Span {
+                run: cluster.run,
+                bytes: cluster.bytes.clone(),
+                glyphs: cluster.glyphs.clone(),
+                advance: cluster.advance,
+                extent: cluster.extent,
+                level,
+                ellipsis
+            });
+        }
+    }
+    spans
+}
+
+/// Puts spans in visual order, by reversing every sequence of spans at or
+/// above each odd level, from the highest level down.
+fn reorder(spans: &mut [Span]) {
+    let lowest_odd = match spans.iter().map(|span| span.level).filter(|level| level % 2 == 1).min() {
+        Some(level) => level,
+        None => return
+    };
+    let highest = spans.iter().map(|span| span.level).max().unwrap_or(0);
+
+    for level in (lowest_odd..highest + 1).rev() {
+        let mut i = 0;
+        while i < spans.len() {
+            let start = i;
+            while i < spans.len() && spans[i].level >= level {
+                i += 1;
+            }
+            spans[start..i].reverse();
+            i += 1;
+        }
+    }
+}
+
+fn fragment<'a>(item: &'a RemoteTextItem, runs: &[ShapedRun<'a>], span: &Span, x: f32, y: f32) -> Fragment<'a> {
+    let shaped = &runs[span.run];
+    Fragment {
+        run: shaped.run,
+        font: shaped.font,
+        face: shaped.face,
+        text: if span.ellipsis { ELLIPSIS } else { &item.text[span.bytes.clone()] },
+        glyphs: match (if span.ellipsis { shaped.ellipsis } else { shaped.glyphs }, span.glyphs.clone()) {
+            (Some(glyphs), Some(range)) => Some(&glyphs[range]),
+            _ => None
+        },
+        rtl: span.level % 2 == 1,
+        x,
+        y,
+        width: 0.0,
+        height: 0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rsx_primitives::rsx_layout::types::LayoutBoundingClientRect;
+    use rsx_primitives::rsx_stylesheet::types::Color;
+
+    use backend::{self, FontTables, RecordedItem, RecordingBackend};
+    use display_list::{RemoteDisplayItem, RemoteTextLayout};
+    use overlay;
+    use shaping::ShapingCache;
+    use text_runs::RunCache;
+
+    use super::*;
+
+    fn text(text: &str, layout: RemoteTextLayout) -> RemoteDisplayItem {
+        let rect = LayoutBoundingClientRect::new(10, 20, 100, 50);
+        RemoteDisplayItem::Text(RemoteTextItem::new(rect, Color::new([255, 0, 0, 255]), text, layout))
+    }
+
+    /// Builds the item with a 16 pixel font that can't be parsed, which makes
+    /// every glyph 8 pixels wide, returning each piece of text pushed, where
+    /// it went and in which direction.
+    fn build_unparsed(item: RemoteDisplayItem) -> Vec<(String, u32, u32, bool)> {
+        let fonts = FontTables::default().font(1, "Sans", None).instance(2, 16);
+        let mut recording = RecordingBackend::new();
+        backend::build(&mut recording, &[item], fonts.fonts(), &mut RunCache::default(), &mut ShapingCache::default());
+        recording
+            .items
+            .into_iter()
+            .map(|item| match item {
+                RecordedItem::Text { rect, text, rtl, .. } => (text, rect.position.left, rect.position.top, rtl),
+                item => panic!("unexpected item {:?}", item)
+            })
+            .collect()
+    }
+
+    fn lines(item: RemoteDisplayItem) -> Vec<(String, u32, u32)> {
+        build_unparsed(item).into_iter().map(|(text, left, top, _)| (text, left, top)).collect()
+    }
+
+    #[test]
+    fn text_wraps_within_its_bounds() {
+        let layout = RemoteTextLayout {
+            wrap: true,
+            line_height: Some(20.0),
+            align: TextAlign::Right,
+            ..Default::default()
+        };
+        assert_eq!(
+            lines(text("aaaa bbbb cccc\ndd", layout)),
+            vec![("aaaa bbbb ".to_string(), 10 + 28, 20), ("cccc".to_string(), 10 + 68, 40), ("dd".to_string(), 10 + 84, 60)]
+        );
+    }
+
+    #[test]
+    fn bidi_text_is_drawn_in_visual_order() {
+        // Text goes to the backend in logical order, with its direction,
+        // rather than reversed for a left to right shaper.
+        assert_eq!(
+            build_unparsed(text("abc \u{5e9}\u{5dc}\u{5d5}\u{5dd} def", Default::default())),
+            vec![
+                ("abc ".to_string(), 10, 20, false),
+                ("\u{5e9}\u{5dc}\u{5d5}\u{5dd}".to_string(), 10 + 32, 20, true),
+                (" def".to_string(), 10 + 64, 20, false),
+            ]
+        );
+
+        // In a right to left paragraph, the paragraph's first word is the
+        // rightmost one.
+        let layout = RemoteTextLayout {
+            direction: TextDirection::Rtl,
+            ..Default::default()
+        };
+        assert_eq!(
+            lines(text("\u{5d0}\u{5d1} abc", layout)),
+            vec![("abc".to_string(), 10, 20), ("\u{5d0}\u{5d1} ".to_string(), 10 + 24, 20)]
+        );
+    }
+
+    #[test]
+    fn right_to_left_text_is_shaped_on_its_own() {
+        let face = Face::from_data_uri(&overlay::font_data_uri()).unwrap();
+        let fonts = FontTables::default().font(1, "Sans", Some(face)).instance(2, 16);
+        let mut shaping = ShapingCache::default();
+        let mut recording = RecordingBackend::new();
+        let item = text("abc \u{5e9}\u{5dc}\u{5d5}\u{5dd} def", Default::default());
+        backend::build(&mut recording, &[item], fonts.fonts(), &mut RunCache::default(), &mut shaping);
+
+        // The run is split where the direction changes, and the Hebrew word
+        // is shaped as a whole rather than a character at a time.
+        let runs = recording
+            .items
+            .iter()
+            .map(|item| match item {
+                &RecordedItem::Glyphs { ref glyphs, rtl, .. } => (glyphs.len(), rtl),
+                item => panic!("unexpected item {:?}", item)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(runs, vec![(4, false), (4, true), (4, false)]);
+        assert_eq!(shaping.len(), 3);
+        assert!(shaping.get(1, 2, true, "\u{5e9}\u{5dc}\u{5d5}\u{5dd}").is_some());
+    }
+
+    #[test]
+    fn vertical_text_is_set_in_columns_from_the_right() {
+        let layout = RemoteTextLayout {
+            wrap: true,
+            line_height: Some(20.0),
+            orientation: TextOrientation::Vertical,
+            ..Default::default()
+        };
+        // Characters are a font size apart along the column, which is 50
+        // pixels tall, and centered across it.
+        let positions = lines(text("\u{65e5}\u{672c}\u{8a9e}\u{306e}\u{6587}", layout))
+            .into_iter()
+            .map(|(_, left, top)| (left, top))
+            .collect::<Vec<_>>();
+        assert_eq!(positions, vec![(10 + 86, 20), (10 + 86, 36), (10 + 86, 52), (10 + 66, 20), (10 + 66, 36)]);
+    }
+
+    #[test]
+    fn overflowing_text_is_clipped_or_ellipsized() {
+        let overflowing = |overflow| {
+            let layout = RemoteTextLayout {
+                overflow,
+                ..Default::default()
+            };
+            lines(text("abcdefghijklmnop", layout))
+        };
+
+        // The text is 128 pixels wide in a 100 pixel wide item, and the
+        // ellipsis takes 8 pixels of its own.
+        assert_eq!(overflowing(TextOverflow::Visible), vec![("abcdefghijklmnop".to_string(), 10, 20)]);
+        assert_eq!(overflowing(TextOverflow::Clip), vec![("abcdefghijkl".to_string(), 10, 20)]);
+        assert_eq!(
+            overflowing(TextOverflow::Ellipsis),
+            vec![("abcdefghijk".to_string(), 10, 20), ("\u{2026}".to_string(), 10 + 88, 20)]
+        );
+        assert_eq!(
+            overflowing(TextOverflow::EllipsisMiddle),
+            vec![("abcdef".to_string(), 10, 20), ("\u{2026}".to_string(), 10 + 48, 20), ("lmnop".to_string(), 10 + 56, 20)]
+        );
+
+        // Only two lines fit in the item's 50 pixels, and the last of them is
+        // ended with an ellipsis.
+        let layout = RemoteTextLayout {
+            line_height: Some(20.0),
+            overflow: TextOverflow::Ellipsis,
+            ..Default::default()
+        };
+        assert_eq!(
+            lines(text("a\nb\nc", layout)),
+            vec![("a".to_string(), 10, 20), ("b".to_string(), 10, 40), ("\u{2026}".to_string(), 10 + 8, 40)]
+        );
+    }
+}
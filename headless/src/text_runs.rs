@@ -0,0 +1,108 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+//! The pieces text items' runs are drawn in. Each run is drawn with the face
+//! of its family that best matches it, split where that face is missing
+//! characters a fallback font has, and split again where its text changes
+//! direction. None of that changes from one frame to the next, so the pieces
+//! are kept by item until the item's text, the fonts or the fallback chain
+//! change, rather than scanning character maps on every frame.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use backend::Fonts;
+use display_list::{RemoteTextItem, RemoteTextRun, TextOrientation};
+use font_fallback;
+use text_layout;
+
+enum Piece {
+    /// All of the item's run at this index, in its own font.
+    Whole(usize),
+    Part(RemoteTextRun)
+}
+
+/// A piece of one of an item's runs, drawn in a single font and direction.
+pub struct ResolvedRun {
+    piece: Piece,
+    pub rtl: bool
+}
+
+impl ResolvedRun {
+    pub fn run<'a>(&'a self, item: &'a RemoteTextItem) -> &'a RemoteTextRun {
+        match self.piece {
+            Piece::Whole(i) => &item.runs[i],
+            Piece::Part(ref run) => run
+        }
+    }
+}
+
+/// Splits `item`'s runs into the pieces they're drawn in.
+pub fn resolve(item: &RemoteTextItem, fonts: &Fonts) -> Vec<ResolvedRun> {
+    // Vertical text is meant for CJK, which has no use for bidi.
+    let levels = match item.layout.orientation {
+        TextOrientation::Vertical => None,
+        TextOrientation::Horizontal => text_layout::bidi_levels(item).0
+    };
+    let mut resolved = vec![];
+    for (i, run) in item.runs.iter().enumerate() {
+        for piece in font_fallback::split_run(item, run, fonts) {
+            for (piece, rtl) in text_layout::split_by_direction(item, piece, levels.as_ref().map(|levels| &levels[..])) {
+                let piece = match piece {
+                    Cow::Borrowed(_) => Piece::Whole(i),
+                    Cow::Owned(run) => Piece::Part(run)
+                };
+                resolved.push(ResolvedRun { piece, rtl });
+            }
+        }
+    }
+    resolved
+}
+
+/// Resolved runs keyed by the index of their item in the display list,
+/// which only ever grows until it's cleared.
+#[derive(Default)]
+pub struct RunCache {
+    items: HashMap<usize, Vec<ResolvedRun>>
+}
+
+impl RunCache {
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// The pieces the item at `index` is drawn in, resolved unless they're
+    /// already cached.
+    pub fn resolve(&mut self, index: usize, item: &RemoteTextItem, fonts: &Fonts) -> &[ResolvedRun] {
+        self.items.entry(index).or_insert_with(|| resolve(item, fonts))
+    }
+
+    /// The pieces the item at `index` is drawn in, if they're cached.
+    pub fn get(&self, index: usize) -> Option<&[ResolvedRun]> {
+        self.items.get(&index).map(|resolved| &resolved[..])
+    }
+
+    /// Forgets the pieces of the item at `index` once its text changes,
+    /// handing them back if they were cached.
+    pub fn invalidate(&mut self, index: usize) -> Option<Vec<ResolvedRun>> {
+        self.items.remove(&index)
+    }
+
+    /// Forgets every item's pieces, once the display list is replaced, or a
+    /// font or the fallback chain changes.
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+}
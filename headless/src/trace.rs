@@ -0,0 +1,212 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json;
+
+use logging::Level;
+
+/// A single event received by the server, as stored in a trace file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TraceEvent {
+    Message(String),
+    Close
+}
+
+/// A trace file is a sequence of JSON lines, one entry per event, each
+/// stamped with the number of milliseconds elapsed since recording started.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceEntry {
+    pub time: u64,
+    pub event: TraceEvent
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayMode {
+    RealTime,
+    Fast,
+    Step
+}
+
+impl FromStr for ReplayMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "realtime" => Ok(ReplayMode::RealTime),
+            "fast" => Ok(ReplayMode::Fast),
+            "step" => Ok(ReplayMode::Step),
+            _ => Err(format!("Unknown replay mode `{}`", s))
+        }
+    }
+}
+
+pub struct Recorder {
+    start: Instant,
+    writer: BufWriter<File>
+}
+
+impl Recorder {
+    pub fn create<P>(path: P) -> io::Result<Recorder>
+    where
+        P: AsRef<Path>
+    {
+        let file = File::create(path)?;
+        Ok(Recorder {
+            start: Instant::now(),
+            writer: BufWriter::new(file)
+        })
+    }
+
+    pub fn record(&mut self, event: TraceEvent) -> io::Result<()> {
+        let time = as_millis(self.start.elapsed());
+        serde_json::to_writer(&mut self.writer, &TraceEntry { time, event })?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()
+    }
+}
+
+pub struct Trace {
+    entries: Vec<TraceEntry>
+}
+
+impl Trace {
+    pub fn open<P>(path: P) -> io::Result<Trace>
+    where
+        P: AsRef<Path>
+    {
+        let file = File::open(path)?;
+        let mut entries = vec![];
+
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push(serde_json::from_str(&line)?);
+        }
+
+        Ok(Trace { entries })
+    }
+
+    pub fn entries(&self) -> &[TraceEntry] {
+        &self.entries
+    }
+}
+
+/// Feeds every event in `trace` to `dispatch`, pacing them according to `mode`:
+/// `RealTime` honors the recorded timestamps, `Fast` sends events back to back,
+/// and `Step` waits for a newline on stdin before each event.
+pub fn replay<F>(trace: &Trace, mode: ReplayMode, mut dispatch: F)
+where
+    F: FnMut(&TraceEvent)
+{
+    let start = Instant::now();
+    let count = trace.entries.len();
+    let stdin = io::stdin();
+
+    for (i, entry) in trace.entries.iter().enumerate() {
+        match mode {
+            ReplayMode::RealTime => {
+                let elapsed = as_millis(start.elapsed());
+                if entry.time > elapsed {
+                    thread::sleep(Duration::from_millis(entry.time - elapsed));
+                }
+            }
+            ReplayMode::Fast => {}
+            ReplayMode::Step => {
+                log_event!(
+                    Level::Info,
+                    "replay",
+                    "Paused, press enter to continue",
+                    event = i + 1,
+                    count = count,
+                    time = entry.time,
+                    next = describe(&entry.event)
+                );
+                let mut line = String::new();
+                if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+                    // Stdin was closed, nothing left to wait on.
+                    return;
+                }
+            }
+        }
+
+        dispatch(&entry.event);
+    }
+
+    log_event!(Level::Info, "replay", "Replayed trace", events = count, millis = as_millis(start.elapsed()));
+}
+
+fn describe(event: &TraceEvent) -> String {
+    match event {
+        &TraceEvent::Message(ref body) => format!("message ({} bytes)", body.len()),
+        &TraceEvent::Close => "close".to_string()
+    }
+}
+
+fn as_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + duration.subsec_nanos() as u64 / 1000000
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+
+    use super::*;
+
+    #[test]
+    fn recorded_events_are_replayed_in_order() {
+        let path = env::temp_dir().join("renderer-process-trace-test.jsonl");
+        {
+            let mut recorder = Recorder::create(&path).unwrap();
+            recorder.record(TraceEvent::Message(r#"{"clear":true}"#.to_string())).unwrap();
+            recorder.record(TraceEvent::Close).unwrap();
+            recorder.record(TraceEvent::Message("{}".to_string())).unwrap();
+        }
+        let trace = Trace::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(trace.entries.windows(2).all(|pair| pair[0].time <= pair[1].time));
+
+        let mut replayed = vec![];
+        replay(&trace, ReplayMode::Fast, |event| replayed.push(describe(event)));
+        assert_eq!(replayed, vec!["message (14 bytes)", "close", "message (2 bytes)"]);
+    }
+
+    #[test]
+    fn blank_lines_are_skipped_and_malformed_ones_rejected() {
+        let path = env::temp_dir().join("renderer-process-trace-lines-test.jsonl");
+        File::create(&path).unwrap().write_all(b"\n{\"time\":5,\"event\":\"Close\"}\n\n").unwrap();
+        let trace = Trace::open(&path).unwrap();
+        assert_eq!(trace.entries.len(), 1);
+        assert_eq!(trace.entries[0].time, 5);
+
+        File::create(&path).unwrap().write_all(b"{\"time\":5}\n").unwrap();
+        let malformed = Trace::open(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(malformed.is_err());
+    }
+
+    #[test]
+    fn replay_modes_are_parsed() {
+        assert_eq!("realtime".parse::<ReplayMode>(), Ok(ReplayMode::RealTime));
+        assert_eq!("fast".parse::<ReplayMode>(), Ok(ReplayMode::Fast));
+        assert_eq!("step".parse::<ReplayMode>(), Ok(ReplayMode::Step));
+        assert!("slow".parse::<ReplayMode>().is_err());
+    }
+}
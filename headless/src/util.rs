@@ -0,0 +1,29 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+use rsx_primitives::rsx_stylesheet::types::Color;
+
+/// Returns the `[red, green, blue, alpha]` components of a color.
+pub fn color_components(color: &Color) -> [u8; 4] {
+    [color.red, color.green, color.blue, color.alpha]
+}
+
+/// Converts a length in pixels to a whole number of them, saturating at the
+/// bounds of `u32` instead of overflowing. NaN is taken as zero.
+pub fn saturating_u32(value: f32) -> u32 {
+    if value >= u32::max_value() as f32 {
+        u32::max_value()
+    } else if value > 0.0 {
+        value as u32
+    } else {
+        0
+    }
+}
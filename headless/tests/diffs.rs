@@ -0,0 +1,216 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+//! Property tests for display list diffs. Random sequences of messages are
+//! applied both to the renderer and to a simple reference model, and the
+//! resulting display lists must match after every message.
+//!
+//! The protocol has no per-item removal, so removal is covered by `clear`.
+//!
+//! When a case fails, the messages of the smallest failing case proptest
+//! shrinks down to are written to `target/diffs-failure.trace`, which can be
+//! replayed with `renderer-process --replay`.
+
+extern crate proptest;
+extern crate renderer_client;
+extern crate renderer_process;
+
+use proptest::prelude::*;
+
+use renderer_client::{Bounds, DisplayListDiff, ItemChange, Message, Rgba, TextRun};
+use renderer_process::display_list::RemoteDisplayItem;
+use renderer_process::protocol::{receive_clear, receive_message};
+use renderer_process::state::REMOTE_DISPLAY_LIST;
+use renderer_process::trace::{Recorder, TraceEvent};
+
+const MAX_INDEX: usize = 12;
+
+#[derive(Debug, Clone, PartialEq)]
+struct ModelItem {
+    bounds: Bounds,
+    text: Option<String>
+}
+
+#[derive(Debug, Clone)]
+struct TestMessage {
+    clear: bool,
+    diffs: Vec<DisplayListDiff>
+}
+
+impl TestMessage {
+    fn to_message(&self) -> Message {
+        let message = if self.clear { Message::new().clear() } else { Message::new() };
+        self.diffs.iter().cloned().fold(message, Message::diff)
+    }
+}
+
+/// Applies `message` the way the renderer is expected to: diffs are applied
+/// in order, and the first invalid one stops the message with an error,
+/// leaving everything before it applied.
+fn apply_model(model: &mut Vec<ModelItem>, message: &TestMessage) -> Result<(), ()> {
+    if message.clear {
+        model.clear();
+    }
+    for diff in &message.diffs {
+        match diff {
+            &DisplayListDiff::AddRect { bounds, .. } | &DisplayListDiff::AddBorder { bounds, .. } => {
+                model.push(ModelItem { bounds, text: None });
+            }
+            &DisplayListDiff::AddText { bounds, ref text, .. } => {
+                model.push(ModelItem {
+                    bounds,
+                    text: Some(text.clone())
+                });
+            }
+            &DisplayListDiff::AddTextRuns { bounds, ref runs, .. } => {
+                model.push(ModelItem {
+                    bounds,
+                    text: Some(runs.iter().map(|run| run.text.as_str()).collect())
+                });
+            }
+            &DisplayListDiff::UpdateSelf { index, ref changes } => {
+                if model.is_empty() {
+                    return Ok(());
+                }
+                let item = model.get_mut(index).ok_or(())?;
+                for change in changes {
+                    match change {
+                        &ItemChange::Text(ref text) => *item.text.as_mut().ok_or(())? = text.clone(),
+                        &ItemChange::Left(left) => item.bounds.left = left,
+                        &ItemChange::Top(top) => item.bounds.top = top,
+                        &ItemChange::Width(width) => item.bounds.width = width,
+                        &ItemChange::Height(height) => item.bounds.height = height
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn renderer_state() -> Vec<ModelItem> {
+    REMOTE_DISPLAY_LIST
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|item| {
+            let rect = item.rect().unwrap();
+            ModelItem {
+                bounds: Bounds::new(rect.position.left, rect.position.top, rect.size.width, rect.size.height),
+                text: match item {
+                    &RemoteDisplayItem::Text(ref text) => Some(text.text.clone()),
+                    _ => None
+                }
+            }
+        })
+        .collect()
+}
+
+fn write_failure_trace(bodies: &[String]) {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/target/diffs-failure.trace");
+    let mut recorder = Recorder::create(path).unwrap();
+    for body in bodies {
+        recorder.record(TraceEvent::Message(body.clone())).unwrap();
+    }
+}
+
+fn bounds() -> BoxedStrategy<Bounds> {
+    (0..2000u32, 0..2000u32, 0..2000u32, 0..2000u32)
+        .prop_map(|(left, top, width, height)| Bounds::new(left, top, width, height))
+        .boxed()
+}
+
+fn color() -> BoxedStrategy<Rgba> {
+    any::<(u8, u8, u8, u8)>()
+        .prop_map(|(red, green, blue, alpha)| Rgba::new(red, green, blue, alpha))
+        .boxed()
+}
+
+fn item_change() -> BoxedStrategy<ItemChange> {
+    prop_oneof![
+        "[a-z ]{0,8}".prop_map(ItemChange::Text),
+        (0..2000u32).prop_map(ItemChange::Left),
+        (0..2000u32).prop_map(ItemChange::Top),
+        (0..2000u32).prop_map(ItemChange::Width),
+        (0..2000u32).prop_map(ItemChange::Height)
+    ].boxed()
+}
+
+fn text_run() -> BoxedStrategy<TextRun> {
+    ("[a-z ]{1,8}", prop::option::of(color()))
+        .prop_map(|(text, color)| TextRun {
+            text,
+            font_key: 1,
+            font_instance_key: 2,
+            color,
+            glyphs: None,
+            decorations: vec![],
+            face: None
+        })
+        .boxed()
+}
+
+fn diff() -> BoxedStrategy<DisplayListDiff> {
+    prop_oneof![
+        (bounds(), color()).prop_map(|(bounds, color)| DisplayListDiff::AddRect { bounds, color }),
+        (bounds(), color()).prop_map(|(bounds, color)| DisplayListDiff::AddBorder {
+            bounds,
+            colors: [color; 4]
+        }),
+        (bounds(), color(), "[a-z ]{0,8}").prop_map(|(bounds, color, text)| DisplayListDiff::AddText {
+            bounds,
+            color,
+            text,
+            font_key: 1,
+            font_instance_key: 2
+        }),
+        (bounds(), color(), prop::collection::vec(text_run(), 2..4))
+            .prop_map(|(bounds, color, runs)| DisplayListDiff::AddTextRuns {
+                bounds,
+                color,
+                runs,
+                layout: Default::default()
+            }),
+        (0..MAX_INDEX, prop::collection::vec(item_change(), 1..4))
+            .prop_map(|(index, changes)| DisplayListDiff::UpdateSelf { index, changes })
+    ].boxed()
+}
+
+fn message() -> BoxedStrategy<TestMessage> {
+    // Clears are rare so that display lists get a chance to grow.
+    (0..20u32, prop::collection::vec(diff(), 0..6))
+        .prop_map(|(clear, diffs)| TestMessage { clear: clear == 0, diffs })
+        .boxed()
+}
+
+proptest! {
+    #[test]
+    fn renderer_matches_model(messages in prop::collection::vec(message(), 1..30)) {
+        receive_clear();
+        let mut model = vec![];
+        let mut bodies = vec![];
+
+        for message in &messages {
+            let body = message.to_message().to_json().to_string();
+            bodies.push(body.clone());
+
+            let expected = apply_model(&mut model, message);
+            let actual = receive_message(&body).map(|_| ());
+            let state = renderer_state();
+
+            if expected.is_ok() != actual.is_ok() || state != model {
+                write_failure_trace(&bodies);
+            }
+            prop_assert_eq!(expected.is_ok(), actual.is_ok(), "message {} was handled differently: {:?}", body, actual);
+            prop_assert_eq!(&state, &model);
+        }
+    }
+}
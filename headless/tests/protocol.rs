@@ -0,0 +1,967 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+//! Protocol conformance tests. Each test starts a `Server` on an ephemeral
+//! port, talks to it through `renderer-client` like a real host would, then
+//! checks the renderer's state. Requests that produce a reply (`dump` or an
+//! error) are used to know when the server is done with a message.
+
+#[macro_use]
+extern crate lazy_static;
+extern crate renderer_client;
+extern crate renderer_process;
+#[macro_use]
+extern crate serde_json;
+extern crate ws;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::{mpsc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use renderer_client::{
+    Bounds,
+    Client,
+    DecorationLine,
+    DisplayListDiff,
+    FontDescriptor,
+    FontStyle,
+    Glyph,
+    ItemChange,
+    Message,
+    ResourceUpdate,
+    Rgba,
+    TextAlign,
+    TextDecoration,
+    TextDirection,
+    TextLayout,
+    TextOrientation,
+    TextOverflow,
+    TextRun
+};
+use renderer_process::backend::{self, FontTables, Fonts, RecordedItem, RecordingBackend};
+use renderer_process::display_list::{self, RemoteDisplayItem, RemoteTextLayout};
+use renderer_process::font_fallback::FontFallback;
+use renderer_process::font_matching;
+use renderer_process::metrics::Stage;
+use renderer_process::overlay;
+use renderer_process::protocol::receive_clear;
+use renderer_process::resources::RemoteResource;
+use renderer_process::server::Server;
+use renderer_process::sfnt::Face;
+use renderer_process::shaping::ShapingCache;
+use renderer_process::state::{
+    DUMP_DIR,
+    FONT_DESCRIPTORS,
+    FONT_DIRS,
+    FONT_FACES,
+    FONT_FALLBACK,
+    FONT_FAMILY_NAME_MAP,
+    FONT_SIZE_MAP,
+    FRAME_METRICS,
+    REGISTERED_RESOURCES,
+    REMOTE_DISPLAY_LIST,
+    REMOTE_RESOURCES,
+    SHAPING_CACHE,
+    SHOULD_SET_WINDOW_POSITION,
+    SHOULD_SET_WINDOW_SIZE,
+    SYSTEM_FONTS,
+    TEXT_RUNS,
+    WINDOW_SIZE
+};
+use renderer_process::system_fonts::SystemFonts;
+use renderer_process::text_runs::RunCache;
+
+const FONT_DATA_URI: &str = "data:font/ttf;base64,AAEAAA==";
+
+lazy_static! {
+    // The renderer's state is global, so tests can't run concurrently.
+    static ref SERIAL: Mutex<()> = Mutex::new(());
+}
+
+fn setup() -> (MutexGuard<'static, ()>, Client) {
+    let guard = SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    reset();
+    (guard, Client::connect(&start_server()).unwrap())
+}
+
+fn reset() {
+    receive_clear();
+    REGISTERED_RESOURCES.lock().unwrap().clear();
+    FONT_FAMILY_NAME_MAP.lock().unwrap().clear();
+    FONT_SIZE_MAP.lock().unwrap().clear();
+    FONT_FACES.lock().unwrap().clear();
+    FONT_DESCRIPTORS.lock().unwrap().clear();
+    *SHAPING_CACHE.lock().unwrap() = ShapingCache::default();
+    *WINDOW_SIZE.lock().unwrap() = None;
+    *SHOULD_SET_WINDOW_POSITION.lock().unwrap() = None;
+    *SHOULD_SET_WINDOW_SIZE.lock().unwrap() = None;
+    *DUMP_DIR.lock().unwrap() = None;
+    FONT_DIRS.lock().unwrap().clear();
+    *SYSTEM_FONTS.lock().unwrap() = SystemFonts::default();
+    *FONT_FALLBACK.lock().unwrap() = FontFallback::default();
+}
+
+fn start_server() -> String {
+    let socket = ws::WebSocket::new(Server::new).unwrap().bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}", socket.local_addr().unwrap());
+    thread::spawn(move || socket.run().unwrap());
+    url
+}
+
+/// Sends `message` along with a dump request, returning the dumped snapshot.
+fn send(client: &Client, message: Message) -> serde_json::Value {
+    let mut reply = client.request(&message.request("dump", serde_json::Value::Null)).unwrap();
+    assert_eq!(reply.get("error"), None, "Unexpected error reply");
+    reply["dump"].take()
+}
+
+/// Sends `message`, which is expected to be rejected, returning the error.
+fn send_invalid(client: &Client, message: Message) -> String {
+    send_raw_invalid(client, &message.to_json().to_string())
+}
+
+fn send_raw_invalid(client: &Client, body: &str) -> String {
+    client.send_raw(body).unwrap();
+    let reply = client.recv().unwrap();
+    reply["error"].as_str().expect("Expected an error reply").to_string()
+}
+
+/// Sends a single `frame` over a connection of its own, bypassing the
+/// client, and returns the reply to it.
+fn send_frame(frame: ws::Message) -> serde_json::Value {
+    let (replies, reply) = mpsc::channel();
+    ws::connect(start_server(), |out| {
+        out.send(frame.clone()).unwrap();
+        let replies = replies.clone();
+        move |msg: ws::Message| {
+            replies.send(msg.into_text().unwrap()).unwrap();
+            out.close(ws::CloseCode::Normal)
+        }
+    }).unwrap();
+    serde_json::from_str(&reply.recv().unwrap()).unwrap()
+}
+
+fn display_list_len() -> usize {
+    REMOTE_DISPLAY_LIST.lock().unwrap().len()
+}
+
+fn bounds() -> Bounds {
+    Bounds::new(10, 20, 100, 50)
+}
+
+fn red() -> Rgba {
+    Rgba::new(255, 0, 0, 255)
+}
+
+fn add_rect() -> DisplayListDiff {
+    DisplayListDiff::AddRect {
+        bounds: bounds(),
+        color: red()
+    }
+}
+
+fn add_text(text: &str) -> DisplayListDiff {
+    DisplayListDiff::AddText {
+        bounds: bounds(),
+        color: red(),
+        text: text.to_string(),
+        font_key: 1,
+        font_instance_key: 2
+    }
+}
+
+fn text_with_layout(text: &str, layout: TextLayout) -> DisplayListDiff {
+    DisplayListDiff::AddTextRuns {
+        bounds: bounds(),
+        color: red(),
+        runs: vec![TextRun::new(text, 1, 2)],
+        layout
+    }
+}
+
+/// "Sans" as font 1, at 16 pixels as instance 2. Without a face, every
+/// glyph is 8 pixels wide.
+fn sans(face: Option<Face>) -> FontTables {
+    FontTables::default().font(1, "Sans", face).instance(2, 16)
+}
+
+fn overlay_face() -> Face {
+    Face::from_data_uri(&overlay::font_data_uri()).unwrap()
+}
+
+/// Builds the display list with fresh caches, returning what was pushed.
+fn build(fonts: &FontTables) -> Vec<RecordedItem> {
+    let mut recording = RecordingBackend::new();
+    backend::build(&mut recording, &REMOTE_DISPLAY_LIST.lock().unwrap(), fonts.fonts(), &mut RunCache::default(), &mut ShapingCache::default());
+    recording.items
+}
+
+/// FreeSans with its character map hidden, so that it has no glyph for any
+/// character and everything has to fall back.
+fn face_without_characters() -> Face {
+    let mut data = vec![];
+    File::open(concat!(env!("CARGO_MANIFEST_DIR"), "/../fonts/FreeSans.ttf"))
+        .unwrap()
+        .read_to_end(&mut data)
+        .unwrap();
+    let tables = ((data[4] as usize) << 8) | data[5] as usize;
+    let record = (0..tables).map(|i| 12 + i * 16).find(|&offset| &data[offset..offset + 4] == b"cmap").unwrap();
+    data[record..record + 4].copy_from_slice(b"xmap");
+    Face::parse(data).unwrap()
+}
+
+fn update(index: usize, changes: Vec<ItemChange>) -> DisplayListDiff {
+    DisplayListDiff::UpdateSelf { index, changes }
+}
+
+fn add_font() -> ResourceUpdate {
+    ResourceUpdate::AddFont {
+        key: 1,
+        data_uri: FONT_DATA_URI.to_string(),
+        descriptor: None
+    }
+}
+
+fn add_font_instance() -> ResourceUpdate {
+    ResourceUpdate::AddFontInstance {
+        key: 1,
+        instance_key: 2,
+        size: 16
+    }
+}
+
+#[test]
+fn clear_empties_display_list_and_pending_resources() {
+    let (_guard, client) = setup();
+    send(&client, Message::new().resource(add_font()).diff(add_rect()).diff(add_rect()));
+    assert_eq!(display_list_len(), 2);
+    assert_eq!(REMOTE_RESOURCES.lock().unwrap().len(), 1);
+
+    send(&client, Message::new().clear());
+    assert_eq!(display_list_len(), 0);
+    assert_eq!(REMOTE_RESOURCES.lock().unwrap().len(), 0);
+}
+
+#[test]
+fn clear_is_applied_before_render() {
+    let (_guard, client) = setup();
+    send(&client, Message::new().diff(add_rect()));
+    send(&client, Message::new().clear().diff(add_rect()));
+    assert_eq!(display_list_len(), 1);
+}
+
+#[test]
+fn position_and_size_are_forwarded_to_the_window() {
+    let (_guard, client) = setup();
+    let dump = send(&client, Message::new().position(-5, 30).size(640, 480));
+    assert_eq!(*SHOULD_SET_WINDOW_POSITION.lock().unwrap(), Some((-5, 30)));
+    assert_eq!(*SHOULD_SET_WINDOW_SIZE.lock().unwrap(), Some((640, 480)));
+    assert_eq!(dump["window_size"], json!([640, 480]));
+}
+
+#[test]
+fn resources_are_queued_in_order() {
+    let (_guard, client) = setup();
+    send(&client, Message::new().resource(add_font()).resource(add_font_instance()));
+
+    let remote_resources = REMOTE_RESOURCES.lock().unwrap();
+    assert_eq!(remote_resources.len(), 2);
+    match remote_resources[0] {
+        RemoteResource::Font(ref font) => {
+            assert_eq!(font.key, 1);
+            assert_eq!(font.data_uri, Some(FONT_DATA_URI.to_string()));
+        }
+        _ => panic!("Expected a font")
+    }
+    match remote_resources[1] {
+        RemoteResource::FontInstance(ref font_instance) => {
+            assert_eq!(font_instance.key, 1);
+            assert_eq!(font_instance.instance_key, 2);
+            assert_eq!(font_instance.size, 16);
+        }
+        _ => panic!("Expected a font instance")
+    }
+}
+
+#[test]
+fn fonts_are_added_from_local_files_and_installed_families() {
+    let (_guard, client) = setup();
+    let fonts_dir = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../fonts"));
+    SYSTEM_FONTS.lock().unwrap().set_dirs(vec![fonts_dir.clone()]);
+    FONT_DIRS.lock().unwrap().push(fonts_dir.canonicalize().unwrap());
+    let font_file = fonts_dir.join("FreeSans.ttf").canonicalize().unwrap();
+
+    let add_file = ResourceUpdate::AddFontFile {
+        key: 1,
+        path: font_file.to_str().unwrap().to_string(),
+        descriptor: None
+    };
+    let add_system_font = ResourceUpdate::AddSystemFont {
+        key: 2,
+        family: "freesans".to_string(),
+        descriptor: None
+    };
+    send(&client, Message::new().resource(add_file).resource(add_system_font));
+    {
+        let remote_resources = REMOTE_RESOURCES.lock().unwrap();
+        let paths = remote_resources
+            .iter()
+            .map(|resource| match resource {
+                &RemoteResource::Font(ref font) => {
+                    assert_eq!(font.data_uri, None);
+                    font.path.clone()
+                }
+                _ => panic!("Expected a font")
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec![Some(font_file.clone()), Some(font_file.clone())]);
+    }
+
+    let unknown_family = ResourceUpdate::AddSystemFont {
+        key: 3,
+        family: "No Such Family".to_string(),
+        descriptor: None
+    };
+    let error = send_invalid(&client, Message::new().resource(unknown_family));
+    assert!(error.contains("No Such Family"), "{}", error);
+    let missing_file = ResourceUpdate::AddFontFile {
+        key: 3,
+        path: fonts_dir.canonicalize().unwrap().join("Missing.ttf").to_str().unwrap().to_string(),
+        descriptor: None
+    };
+    let error = send_invalid(&client, Message::new().resource(missing_file));
+    assert!(error.starts_with("I/O error"), "{}", error);
+
+    // Files outside the font directories can't be read, however the path
+    // gets there.
+    let manifest = fonts_dir.join("../headless/Cargo.toml").canonicalize().unwrap();
+    for path in &[manifest.clone(), fonts_dir.join("../headless/Cargo.toml"), PathBuf::from("FreeSans.ttf")] {
+        let outside = ResourceUpdate::AddFontFile {
+            key: 3,
+            path: path.to_str().unwrap().to_string(),
+            descriptor: None
+        };
+        let error = send_invalid(&client, Message::new().resource(outside));
+        assert!(error.contains("--font-dir"), "{}", error);
+    }
+}
+
+#[test]
+fn dumps_embed_fonts_added_from_files() {
+    let (_guard, client) = setup();
+    let fonts_dir = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../fonts")).canonicalize().unwrap();
+    FONT_DIRS.lock().unwrap().push(fonts_dir.clone());
+    let add_file = ResourceUpdate::AddFontFile {
+        key: 1,
+        path: fonts_dir.join("FreeSans.ttf").to_str().unwrap().to_string(),
+        descriptor: None
+    };
+    send(&client, Message::new().resource(add_file));
+    // Registration is the runtime's job, so the font is moved over the way
+    // it would.
+    let pending = REMOTE_RESOURCES.lock().unwrap().drain(..).collect::<Vec<_>>();
+    REGISTERED_RESOURCES.lock().unwrap().extend(pending);
+
+    let dump = send(&client, Message::new());
+    let font = &dump["resources"][0]["Font"];
+    assert_eq!(font["path"], serde_json::Value::Null);
+    assert!(font["data_uri"].as_str().unwrap().starts_with("data:font/ttf;base64,"));
+    // The registered font still refers to its file.
+    match REGISTERED_RESOURCES.lock().unwrap()[0] {
+        RemoteResource::Font(ref font) => assert!(font.path.is_some() && font.data_uri.is_none()),
+        _ => panic!("Expected a font")
+    }
+}
+
+#[test]
+fn fonts_are_added_with_their_descriptors() {
+    let (_guard, client) = setup();
+    let bold_italic = FontDescriptor {
+        weight: 700,
+        style: FontStyle::Italic,
+        ..Default::default()
+    };
+    let add_font = ResourceUpdate::AddFont {
+        key: 1,
+        data_uri: FONT_DATA_URI.to_string(),
+        descriptor: Some(bold_italic)
+    };
+    send(&client, Message::new().resource(add_font));
+    match REMOTE_RESOURCES.lock().unwrap()[0] {
+        RemoteResource::Font(ref font) => {
+            assert_eq!((font.weight, font.style, font.stretch), (Some(700), Some(font_matching::FontStyle::Italic), Some(100.0)));
+            // The font can't be parsed, so everything else about it is normal.
+            let descriptor = font.descriptor(None);
+            assert_eq!((descriptor.weight, descriptor.style), (700, font_matching::FontStyle::Italic));
+        }
+        _ => panic!("Expected a font")
+    }
+
+    for &(field, value) in &[("weight", json!(0)), ("weight", json!(1001)), ("style", json!("slanted")), ("stretch", json!(-50))] {
+        let mut font = json!({ "key": 2, "data_uri": FONT_DATA_URI });
+        font[field] = value;
+        let body = json!({ "resources": [{ "AddFont": font }] }).to_string();
+        let error = send_raw_invalid(&client, &body);
+        assert!(error.contains(field), "{}", error);
+    }
+}
+
+#[test]
+fn runs_are_drawn_with_the_closest_face_of_their_family() {
+    let (_guard, client) = setup();
+    let bold = TextRun {
+        face: Some(FontDescriptor {
+            weight: 700,
+            ..Default::default()
+        }),
+        ..TextRun::new("Hi", 1, 2)
+    };
+    send(&client, Message::new().diff(DisplayListDiff::AddTextRuns {
+        bounds: bounds(),
+        color: red(),
+        runs: vec![bold, TextRun::new("Hi", 1, 2)],
+        layout: Default::default()
+    }));
+
+    // Only the bold face of the family can be parsed, so runs drawn with it
+    // come out shaped and the others don't.
+    let mut fonts = sans(None).font(3, "Sans", Some(overlay_face()));
+    let bold_face = font_matching::FontDescriptor {
+        weight: 700,
+        ..Default::default()
+    };
+    fonts.descriptors.insert(3, bold_face);
+    assert_eq!(fonts.fonts().match_face(1, None), 1);
+    let items = build(&fonts);
+    assert_eq!(items.len(), 2);
+    match items[0] {
+        RecordedItem::Glyphs { ref glyphs, .. } => assert_eq!(glyphs.len(), 2),
+        _ => panic!("Expected glyphs")
+    }
+    match items[1] {
+        RecordedItem::Text { ref text, .. } => assert_eq!(text, "Hi"),
+        _ => panic!("Expected text")
+    }
+}
+
+#[test]
+fn render_adds_rects_borders_and_text() {
+    let (_guard, client) = setup();
+    let border = DisplayListDiff::AddBorder {
+        bounds: bounds(),
+        colors: [red(), Rgba::new(0, 255, 0, 255), Rgba::new(0, 0, 255, 255), Rgba::new(0, 0, 0, 128)]
+    };
+    send(
+        &client,
+        Message::new()
+            .resource(add_font())
+            .resource(add_font_instance())
+            .diff(add_rect())
+            .diff(border)
+            .diff(add_text("Hello"))
+    );
+
+    let remote_display_list = REMOTE_DISPLAY_LIST.lock().unwrap();
+    assert_eq!(remote_display_list.len(), 3);
+    match remote_display_list[0] {
+        RemoteDisplayItem::Rect(ref rect) => {
+            assert_eq!((rect.rect.position.left, rect.rect.position.top), (10, 20));
+            assert_eq!((rect.rect.size.width, rect.rect.size.height), (100, 50));
+        }
+        _ => panic!("Expected a rect")
+    }
+    match remote_display_list[1] {
+        RemoteDisplayItem::Border(ref border) => assert_eq!(border.widths, [1, 1, 1, 1]),
+        _ => panic!("Expected a border")
+    }
+    match remote_display_list[2] {
+        RemoteDisplayItem::Text(ref text) => {
+            assert_eq!(text.text, "Hello");
+            assert_eq!(text.runs.len(), 1);
+            assert_eq!((text.runs[0].font_key, text.runs[0].font_instance_key), (1, 2));
+            assert_eq!((text.runs[0].start, text.runs[0].end), (0, 5));
+        }
+        _ => panic!("Expected text")
+    }
+}
+
+#[test]
+fn text_runs_keep_their_own_fonts_and_colors() {
+    let (_guard, client) = setup();
+    let blue = Rgba::new(0, 0, 255, 255);
+    let runs = vec![
+        TextRun::new("Hello ", 1, 2),
+        TextRun {
+            color: Some(blue),
+            ..TextRun::new("world", 3, 4)
+        },
+    ];
+    send(&client, Message::new().diff(DisplayListDiff::AddTextRuns {
+        bounds: bounds(),
+        color: red(),
+        runs,
+        layout: Default::default()
+    }));
+
+    match REMOTE_DISPLAY_LIST.lock().unwrap()[0] {
+        RemoteDisplayItem::Text(ref text) => {
+            assert_eq!(text.text, "Hello world");
+            assert_eq!(text.runs.len(), 2);
+            assert_eq!((text.runs[0].font_key, text.runs[0].font_instance_key), (1, 2));
+            assert_eq!((text.runs[1].font_key, text.runs[1].font_instance_key), (3, 4));
+            assert_eq!(text.run_text(&text.runs[0]), "Hello ");
+            assert_eq!(text.run_text(&text.runs[1]), "world");
+
+            // Runs without a color of their own take the item's.
+            let item_color = serde_json::to_value(&text.color).unwrap();
+            assert_eq!(serde_json::to_value(&text.runs[0].color).unwrap(), item_color);
+            assert_ne!(serde_json::to_value(&text.runs[1].color).unwrap(), item_color);
+        }
+        _ => panic!("Expected text")
+    }
+
+    // There's no telling where the old runs went in new text, so the first
+    // one takes it all.
+    send(&client, Message::new().diff(update(0, vec![ItemChange::Text("Bye".to_string())])));
+    match REMOTE_DISPLAY_LIST.lock().unwrap()[0] {
+        RemoteDisplayItem::Text(ref text) => {
+            assert_eq!(text.runs.len(), 1);
+            assert_eq!((text.runs[0].font_key, text.runs[0].start, text.runs[0].end), (1, 0, 3));
+        }
+        _ => panic!("Expected text")
+    }
+}
+
+#[test]
+fn update_self_changes_text_and_bounds() {
+    let (_guard, client) = setup();
+    send(&client, Message::new().diff(add_rect()).diff(add_text("Hello")));
+    send(
+        &client,
+        Message::new()
+            .diff(update(0, vec![ItemChange::Left(1), ItemChange::Top(2)]))
+            .diff(update(1, vec![ItemChange::Text("World".to_string()), ItemChange::Width(3), ItemChange::Height(4)]))
+    );
+
+    let remote_display_list = REMOTE_DISPLAY_LIST.lock().unwrap();
+    let rect = remote_display_list[0].rect().unwrap();
+    assert_eq!((rect.position.left, rect.position.top, rect.size.width, rect.size.height), (1, 2, 100, 50));
+    let rect = remote_display_list[1].rect().unwrap();
+    assert_eq!((rect.position.left, rect.position.top, rect.size.width, rect.size.height), (10, 20, 3, 4));
+    match remote_display_list[1] {
+        RemoteDisplayItem::Text(ref text) => assert_eq!(text.text, "World"),
+        _ => panic!("Expected text")
+    }
+}
+
+#[test]
+fn update_self_on_empty_display_list_is_ignored() {
+    // Hosts may send updates for a page the renderer hasn't seen being built.
+    let (_guard, client) = setup();
+    send(&client, Message::new().diff(update(3, vec![ItemChange::Left(1)])));
+    assert_eq!(display_list_len(), 0);
+}
+
+#[test]
+fn dump_reply_contains_display_list() {
+    let (_guard, client) = setup();
+    let dump = send(&client, Message::new().diff(add_rect()));
+    assert_eq!(dump["display_list"].as_array().unwrap().len(), 1);
+    assert!(dump["display_list"][0].get("Rect").is_some());
+}
+
+#[test]
+fn dumps_are_only_written_within_the_dump_dir() {
+    let (_guard, client) = setup();
+    let dump = |path: &str| Message::new().request("dump", json!({ "path": path }));
+
+    // Without a directory to write to, clients can't have files written.
+    let error = send_invalid(&client, dump("frame.json"));
+    assert!(error.contains("--dump-dir"), "{}", error);
+
+    let dump_dir = env::temp_dir().join("renderer-process-dumps");
+    fs::create_dir_all(&dump_dir).unwrap();
+    *DUMP_DIR.lock().unwrap() = Some(dump_dir.clone());
+    for path in &["../frame.json", "/tmp/frame.json", "nested/../../frame.json"] {
+        let error = send_invalid(&client, dump(path));
+        assert!(error.contains("--dump-dir"), "{}", error);
+    }
+    let reply = client.request(&dump("frame.json")).unwrap();
+    assert_eq!(reply["dump"]["path"], json!("frame.json"));
+    assert!(dump_dir.join("frame.json").is_file());
+    fs::remove_dir_all(&dump_dir).unwrap();
+}
+
+#[test]
+fn unknown_key_is_rejected_without_side_effects() {
+    let (_guard, client) = setup();
+    let error = send_invalid(&client, Message::new().diff(add_rect()).request("bogus", json!(true)));
+    assert!(error.contains("bogus"), "{}", error);
+    assert_eq!(display_list_len(), 0);
+}
+
+#[test]
+fn unknown_diff_is_rejected() {
+    let (_guard, client) = setup();
+    let error = send_raw_invalid(&client, r#"{ "render": [{ "AddVideo": {} }] }"#);
+    assert!(error.contains("AddVideo"), "{}", error);
+    assert_eq!(display_list_len(), 0);
+}
+
+#[test]
+fn requests_drop_replies_to_earlier_messages() {
+    let (_guard, client) = setup();
+    client.send_raw(r#"{ "video": true }"#).unwrap();
+    // Give the error reply time to be queued before the request is sent.
+    thread::sleep(Duration::from_millis(100));
+    let reply = client.request(&Message::new().request("dump", serde_json::Value::Null)).unwrap();
+    assert_eq!(reply.get("error"), None);
+    assert!(reply.get("dump").is_some());
+}
+
+#[test]
+fn binary_frames_are_rejected() {
+    let (_guard, _client) = setup();
+    let reply = send_frame(ws::Message::Binary(br#"{ "render": [] }"#.to_vec()));
+    let error = reply["error"].as_str().expect("Expected an error reply");
+    assert!(error.contains("text frames"), "{}", error);
+}
+
+#[test]
+fn unknown_resource_is_rejected() {
+    let (_guard, client) = setup();
+    let error = send_raw_invalid(&client, r#"{ "resources": [{ "AddImage": {} }] }"#);
+    assert!(error.contains("AddImage"), "{}", error);
+    assert_eq!(REMOTE_RESOURCES.lock().unwrap().len(), 0);
+}
+
+#[test]
+fn fonts_cannot_be_added_under_the_fallback_fonts_keys() {
+    let (_guard, client) = setup();
+    let font = ResourceUpdate::AddFont {
+        key: u64::max_value(),
+        data_uri: FONT_DATA_URI.to_string(),
+        descriptor: None
+    };
+    let error = send_invalid(&client, Message::new().resource(font));
+    assert!(error.contains("reserved"), "{}", error);
+    assert_eq!(REMOTE_RESOURCES.lock().unwrap().len(), 0);
+}
+
+#[test]
+fn missing_fields_are_rejected() {
+    let (_guard, client) = setup();
+    let mut body = Message::new().diff(add_rect()).to_json();
+    for (_, diff) in body["render"][0].as_object_mut().unwrap() {
+        diff.as_object_mut().unwrap().remove("bounds");
+    }
+    let error = send_raw_invalid(&client, &body.to_string());
+    assert!(error.contains("bounds"), "{}", error);
+    assert_eq!(display_list_len(), 0);
+}
+
+#[test]
+fn font_instance_for_missing_font_is_rejected() {
+    let (_guard, client) = setup();
+    let error = send_invalid(&client, Message::new().resource(add_font_instance()));
+    assert!(error.contains("font"), "{}", error);
+    assert_eq!(REMOTE_RESOURCES.lock().unwrap().len(), 0);
+}
+
+#[test]
+fn font_instance_for_font_from_earlier_message_is_accepted() {
+    let (_guard, client) = setup();
+    send(&client, Message::new().resource(add_font()));
+    send(&client, Message::new().resource(add_font_instance()));
+    assert_eq!(REMOTE_RESOURCES.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn out_of_range_index_is_rejected() {
+    let (_guard, client) = setup();
+    send(&client, Message::new().diff(add_rect()));
+    let error = send_invalid(&client, Message::new().diff(update(1, vec![ItemChange::Left(0)])));
+    assert!(error.contains("index 1"), "{}", error);
+
+    let rect = REMOTE_DISPLAY_LIST.lock().unwrap()[0].rect().unwrap();
+    assert_eq!(rect.position.left, 10);
+}
+
+#[test]
+fn text_change_on_non_text_item_is_rejected() {
+    let (_guard, client) = setup();
+    send(&client, Message::new().diff(add_rect()));
+    let error = send_invalid(&client, Message::new().diff(update(0, vec![ItemChange::Text("Nope".to_string())])));
+    assert!(error.contains("index 0"), "{}", error);
+}
+
+#[test]
+fn malformed_json_is_rejected_and_server_keeps_running() {
+    let (_guard, client) = setup();
+    send_raw_invalid(&client, "{ not json");
+    send(&client, Message::new().diff(add_rect()));
+    assert_eq!(display_list_len(), 1);
+}
+
+#[test]
+fn text_with_unregistered_font_is_skipped_when_building() {
+    let (_guard, client) = setup();
+    send(&client, Message::new().diff(add_rect()).diff(add_text("Hello")));
+
+    let items = build(&FontTables::default());
+    assert_eq!(items.len(), 1);
+    match items[0] {
+        RecordedItem::Rect { .. } => {}
+        _ => panic!("Expected a rect")
+    }
+}
+
+#[test]
+fn pre_shaped_runs_are_pushed_as_glyphs() {
+    let (_guard, client) = setup();
+    let runs = vec![
+        TextRun {
+            glyphs: Some(vec![Glyph::new(43, 9.5), Glyph::new(72, 8.0)]),
+            ..TextRun::new("Hi", 1, 2)
+        },
+        TextRun::new(" there", 1, 2),
+    ];
+    send(&client, Message::new().diff(DisplayListDiff::AddTextRuns {
+        bounds: bounds(),
+        color: red(),
+        runs,
+        layout: Default::default()
+    }));
+
+    let items = build(&sans(None));
+    assert_eq!(items.len(), 2);
+    match items[0] {
+        RecordedItem::Glyphs { ref glyphs, .. } => {
+            assert_eq!(glyphs.iter().map(|glyph| glyph.index).collect::<Vec<_>>(), vec![43, 72]);
+        }
+        _ => panic!("Expected glyphs")
+    }
+    match items[1] {
+        // Only the run without glyphs is left for the renderer to shape, and
+        // it starts where the shaped one's advances end.
+        RecordedItem::Text { rect, ref text, .. } => {
+            assert_eq!(text, " there");
+            assert_eq!(rect.position.left, 10 + 18);
+        }
+        _ => panic!("Expected text")
+    }
+
+    // Changing the text invalidates the glyphs.
+    send(&client, Message::new().diff(update(0, vec![ItemChange::Text("Bye".to_string())])));
+    match REMOTE_DISPLAY_LIST.lock().unwrap()[0] {
+        RemoteDisplayItem::Text(ref text) => assert!(text.runs[0].glyphs.is_none()),
+        _ => panic!("Expected text")
+    }
+}
+
+#[test]
+fn text_layout_and_decorations_are_received() {
+    let (_guard, client) = setup();
+    let layout = TextLayout {
+        wrap: true,
+        line_height: Some(20.0),
+        align: TextAlign::Right,
+        direction: TextDirection::Rtl,
+        orientation: TextOrientation::Vertical,
+        overflow: TextOverflow::EllipsisMiddle
+    };
+    let run = TextRun {
+        decorations: vec![
+            TextDecoration::new(DecorationLine::Underline),
+            TextDecoration {
+                color: Some(Rgba::new(0, 0, 255, 255)),
+                thickness: Some(3.0),
+                ..TextDecoration::new(DecorationLine::LineThrough)
+            },
+        ],
+        ..TextRun::new("abc", 1, 2)
+    };
+    send(&client, Message::new().diff(text_with_layout("abc", layout)).diff(DisplayListDiff::AddTextRuns {
+        bounds: bounds(),
+        color: red(),
+        runs: vec![run],
+        layout: Default::default()
+    }));
+
+    let mut remote_display_list = REMOTE_DISPLAY_LIST.lock().unwrap();
+    assert_eq!(
+        remote_display_list[0].as_text().unwrap().layout,
+        RemoteTextLayout {
+            wrap: true,
+            line_height: Some(20.0),
+            align: display_list::TextAlign::Right,
+            direction: display_list::TextDirection::Rtl,
+            orientation: display_list::TextOrientation::Vertical,
+            overflow: display_list::TextOverflow::EllipsisMiddle
+        }
+    );
+    let decorations = &remote_display_list[1].as_text().unwrap().runs[0].decorations;
+    assert_eq!(decorations.len(), 2);
+    assert_eq!((decorations[0].line, decorations[0].thickness), (display_list::DecorationLine::Underline, None));
+    assert_eq!((decorations[1].line, decorations[1].thickness), (display_list::DecorationLine::LineThrough, Some(3.0)));
+    assert!(decorations[1].color.is_some());
+}
+
+#[test]
+fn characters_missing_from_a_font_fall_back_through_the_chain() {
+    let (_guard, client) = setup();
+    send(&client, Message::new().diff(add_text("Hi")));
+
+    let mut tables = sans(Some(face_without_characters())).font(3, "Fallback", Some(overlay_face()));
+    tables.fallback = vec![3];
+    let fonts = tables.fonts();
+    for &(fonts, family_name) in &[(fonts, "Fallback"), (Fonts { fallback: &[], ..fonts }, "Sans")] {
+        let mut recording = RecordingBackend::new();
+        backend::build(&mut recording, &REMOTE_DISPLAY_LIST.lock().unwrap(), fonts, &mut RunCache::default(), &mut ShapingCache::default());
+        assert_eq!(recording.items.len(), 1);
+        match recording.items[0] {
+            RecordedItem::Glyphs { family_name: ref drawn_with, .. } => assert_eq!(drawn_with, family_name),
+            _ => panic!("Expected glyphs")
+        }
+    }
+}
+
+#[test]
+fn fallback_chain_is_set_per_session_and_reported_in_dumps() {
+    let (_guard, client) = setup();
+    let add_fallback_font = ResourceUpdate::AddFont {
+        key: 3,
+        data_uri: FONT_DATA_URI.to_string(),
+        descriptor: None
+    };
+    let dump = send(&client, Message::new().resource(add_font()).resource(add_fallback_font).fallback(vec![3]));
+    assert_eq!(dump["font_fallback"]["session"], json!([3]));
+
+    // Registration is the runtime's job, so the font tables are filled in
+    // the way it would.
+    FONT_FAMILY_NAME_MAP.lock().unwrap().extend(vec![(1, "Sans".to_string()), (3, "Fallback".to_string())]);
+    FONT_FACES.lock().unwrap().insert(1, face_without_characters());
+    FONT_FACES.lock().unwrap().insert(3, Face::from_data_uri(&overlay::font_data_uri()).unwrap());
+    let dump = send(&client, Message::new().diff(add_text("Hi")));
+    assert_eq!(dump["fallback_spans"], json!([{ "item": 0, "run": 0, "start": 0, "end": 2, "font_key": 3 }]));
+
+    let error = send_invalid(&client, Message::new().fallback(vec![3, 7]));
+    assert!(error.contains("key 7"), "{}", error);
+    let dump = send(&client, Message::new().clear());
+    assert_eq!(dump["font_fallback"]["session"], json!([]));
+}
+
+#[test]
+fn resolved_runs_are_kept_until_the_text_or_fonts_change() {
+    let (_guard, client) = setup();
+    send(&client, Message::new().resource(add_font()).diff(add_text("Hello")));
+
+    let tables = sans(None);
+    let fonts = tables.fonts();
+    let build = || {
+        let mut recording = RecordingBackend::new();
+        backend::build(&mut recording, &REMOTE_DISPLAY_LIST.lock().unwrap(), fonts, &mut TEXT_RUNS.lock().unwrap(), &mut ShapingCache::default());
+    };
+    build();
+    assert_eq!(TEXT_RUNS.lock().unwrap().len(), 1);
+
+    send(&client, Message::new().diff(update(0, vec![ItemChange::Text("Bye".to_string())])));
+    assert!(TEXT_RUNS.lock().unwrap().is_empty());
+
+    // Another fallback chain may draw the same text with other fonts.
+    build();
+    send(&client, Message::new().fallback(vec![1]));
+    assert!(TEXT_RUNS.lock().unwrap().is_empty());
+}
+
+#[test]
+fn unchanged_text_is_shaped_once() {
+    let (_guard, client) = setup();
+    send(&client, Message::new().diff(add_text("Hello")));
+
+    let fonts = sans(Some(overlay_face()));
+    for _ in 0..3 {
+        let mut recording = RecordingBackend::new();
+        backend::build(&mut recording, &REMOTE_DISPLAY_LIST.lock().unwrap(), fonts.fonts(), &mut TEXT_RUNS.lock().unwrap(), &mut SHAPING_CACHE.lock().unwrap());
+        match recording.items[0] {
+            RecordedItem::Glyphs { ref glyphs, .. } => assert_eq!(glyphs.len(), 5),
+            _ => panic!("Expected glyphs")
+        }
+    }
+
+    let reply = client.request(&Message::new().request("stats", json!({ "reset": true }))).unwrap();
+    let shaping = &reply["stats"]["shaping"];
+    assert_eq!((shaping["hits"].clone(), shaping["misses"].clone()), (json!(2), json!(1)));
+    assert_eq!(shaping["entries"], json!(1));
+
+    // The old text is dropped as soon as the item shows something else.
+    send(&client, Message::new().diff(update(0, vec![ItemChange::Text("Bye".to_string())])));
+    assert!(SHAPING_CACHE.lock().unwrap().is_empty());
+    assert_eq!(SHAPING_CACHE.lock().unwrap().hit_rate(), 0.0);
+}
+
+#[test]
+fn shaping_is_kept_while_another_item_shows_the_same_text() {
+    let (_guard, client) = setup();
+    send(&client, Message::new().diff(add_text("Hello")).diff(add_text("Hello")));
+
+    let fonts = sans(Some(overlay_face()));
+    let mut recording = RecordingBackend::new();
+    backend::build(&mut recording, &REMOTE_DISPLAY_LIST.lock().unwrap(), fonts.fonts(), &mut TEXT_RUNS.lock().unwrap(), &mut SHAPING_CACHE.lock().unwrap());
+    assert_eq!(SHAPING_CACHE.lock().unwrap().len(), 1);
+
+    send(&client, Message::new().diff(update(0, vec![ItemChange::Text("Bye".to_string())])));
+    assert_eq!(SHAPING_CACHE.lock().unwrap().len(), 1);
+    send(&client, Message::new().diff(update(1, vec![ItemChange::Text("Bye".to_string())])));
+    assert!(SHAPING_CACHE.lock().unwrap().is_empty());
+}
+
+#[test]
+fn stats_report_each_stage_and_can_be_reset() {
+    let (_guard, client) = setup();
+    let stats = |reset: bool| client.request(&Message::new().request("stats", json!({ "reset": reset }))).unwrap()["stats"].take();
+    stats(true);
+
+    {
+        let mut frame_metrics = FRAME_METRICS.lock().unwrap();
+        frame_metrics.record(Stage::Build, Duration::new(0, 300_000));
+        frame_metrics.frame_built();
+        frame_metrics.runner_polled();
+    }
+    let summary = stats(true);
+    assert_eq!(summary["frames"], json!(1));
+    assert_eq!(summary["build"]["mean_us"], json!(300));
+    assert!(summary["shaping"].is_object());
+
+    let summary = stats(false);
+    assert_eq!(summary["frames"], json!(0));
+    assert_eq!(summary["build"], json!({ "count": 0 }));
+}
+
+#[test]
+fn closing_the_connection_clears_the_display_list() {
+    let (_guard, client) = setup();
+    send(&client, Message::new().diff(add_rect()));
+    drop(client);
+
+    // The close is handled on the server's thread, some time after the client is gone.
+    for _ in 0..100 {
+        if display_list_len() == 0 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(display_list_len(), 0);
+}
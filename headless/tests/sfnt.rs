@@ -0,0 +1,113 @@
+/*
+Copyright 2016 Mozilla
+Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+this file except in compliance with the License. You may obtain a copy of the
+License at http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software distributed
+under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+CONDITIONS OF ANY KIND, either express or implied. See the License for the
+specific language governing permissions and limitations under the License.
+*/
+
+//! Checks the font reader against FreeSans, whose tables were read out
+//! independently, and against fonts it has to reject.
+
+extern crate base64;
+extern crate renderer_process;
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use renderer_process::sfnt::{Face, Style, VerticalMetrics};
+
+const FREE_SANS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../fonts/FreeSans.ttf");
+
+fn free_sans_data() -> Vec<u8> {
+    let mut data = vec![];
+    File::open(FREE_SANS).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+#[test]
+fn reads_metrics() {
+    let face = Face::from_path(Path::new(FREE_SANS)).unwrap();
+    assert_eq!(face.units_per_em(), 1000);
+    assert_eq!(face.vertical_metrics(), VerticalMetrics {
+        ascender: 1000,
+        descender: -300,
+        underline_position: -176,
+        underline_thickness: 50,
+        strikeout_position: 258,
+        strikeout_thickness: 49
+    });
+    assert_eq!(face.scale(500, 16), 8.0);
+}
+
+#[test]
+fn reads_names_and_style() {
+    let face = Face::from_path(Path::new(FREE_SANS)).unwrap();
+    assert_eq!(face.family_name(), Some("FreeSans".to_string()));
+    assert_eq!(face.subfamily_name(), Some("Medium".to_string()));
+    assert_eq!(face.style(), Style {
+        weight_class: 400,
+        width_class: 5,
+        italic: false,
+        oblique: false
+    });
+}
+
+#[test]
+fn maps_characters_to_glyphs_and_advances() {
+    let face = Face::from_path(Path::new(FREE_SANS)).unwrap();
+    assert_eq!(face.glyph_index('A'), Some(36));
+    assert_eq!(face.glyph_index(' '), Some(3));
+    assert_eq!(face.glyph_index('\u{5d0}'), Some(1213));
+    assert_eq!(face.glyph_advance(36), 667);
+    assert_eq!(face.glyph_advance(1213), 640);
+
+    // Characters outside the font, in and past the basic multilingual plane,
+    // fall back to the missing glyph's advance.
+    assert_eq!(face.glyph_index('\u{4e00}'), None);
+    assert_eq!(face.glyph_index('\u{1f600}'), None);
+    assert_eq!(face.char_width('\u{4e00}', 1000), 700.0);
+    assert_eq!(face.text_width("A A", 1000), 667.0 + 278.0 + 667.0);
+
+    // Glyphs past the last metric share its advance rather than reading
+    // past the table.
+    assert_eq!(face.glyph_advance(u16::max_value()), face.glyph_advance(5288));
+}
+
+#[test]
+fn parses_data_uris() {
+    let data = free_sans_data();
+    let data_uri = format!("data:font/ttf;base64,{}", base64::encode(&data));
+    let face = Face::from_data_uri(&data_uri).unwrap();
+    assert_eq!(face.glyph_index('A'), Some(36));
+
+    assert!(Face::from_data_uri("data:font/ttf;base64,!!!").is_none());
+    assert!(Face::from_data_uri("FreeSans.ttf").is_none());
+}
+
+#[test]
+fn rejects_what_isnt_a_font() {
+    assert!(Face::parse(vec![]).is_none());
+    assert!(Face::parse(b"ttcf".to_vec()).is_none());
+    assert!(Face::parse(b"not a font at all, just some text".to_vec()).is_none());
+    assert!(Face::from_path(Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"))).is_none());
+}
+
+#[test]
+fn survives_truncated_fonts() {
+    let data = free_sans_data();
+    let mut length = 0;
+    while length < data.len() {
+        if let Some(face) = Face::parse(data[..length].to_vec()) {
+            face.glyph_index('A');
+            face.glyph_index('\u{1f600}');
+            face.glyph_advance(36);
+            face.family_name();
+        }
+        length = length * 2 + 1;
+    }
+}